@@ -0,0 +1,20 @@
+import time
+
+start_time = time.time()
+
+long = "raz"
+for i in range(0, 14):
+    long = long + long
+
+def same(text):
+    return text
+
+count = 0
+for i in range(0, 100000):
+    copy = long
+    if same(copy) == long:
+        count = count + 1
+
+time_took = time.time() - start_time
+
+print(time_took)
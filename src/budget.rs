@@ -0,0 +1,104 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::scanner::Span;
+
+// how much a script gets to run before it's stopped, for running code you dont trust.
+// a step is a statement or an expression for the interpreter and an instruction for the vm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    pub max_steps: Option<u64>,
+    pub deadline: Option<Instant>,
+}
+
+// how far a script got before its budget ran out, `Meter::take_exceeded` hands it over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exceeded {
+    pub steps: u64,
+    pub elapsed: Duration,
+    // the statement, expression or instruction it was stopped at
+    pub span: Span,
+}
+
+// one interpreter's or vm's budget and how much of it is gone. only the one running counts
+// its steps, it's swapped in with `switch` for that and swapped back out after
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Meter {
+    budget: Budget,
+    started: Option<Instant>,
+    steps: u64,
+    exceeded: Option<Exceeded>,
+}
+
+impl Meter {
+    // the steps and the time are counted from here on
+    pub fn new(budget: Budget) -> Self {
+        Self { budget, started: Some(Instant::now()), steps: 0, exceeded: None }
+    }
+
+    // `Some` when the last error was the budget running out, tells it apart from the script's own errors
+    pub fn take_exceeded(self: &mut Self) -> Option<Exceeded> {
+        self.exceeded.take()
+    }
+}
+
+// looking at the clock every step would cost more than the step itself
+const CLOCK_EVERY: u64 = 1024;
+
+// the meter of whatever is running right now
+thread_local! {
+    static BUDGET: Cell<Budget> = const { Cell::new(Budget { max_steps: None, deadline: None }) };
+    static STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+    static STEPS: Cell<u64> = const { Cell::new(0) };
+    // until this step `step` doesnt have to look at anything else
+    static NEXT_CHECK: Cell<u64> = const { Cell::new(u64::MAX) };
+    static EXCEEDED: Cell<Option<Exceeded>> = const { Cell::new(None) };
+}
+
+// steps get counted against `meter` from now on, what they were counted against gets handed back
+pub fn switch(meter: Meter) -> Meter {
+    let outer = Meter {
+        budget: BUDGET.replace(meter.budget),
+        started: STARTED.replace(meter.started),
+        steps: STEPS.replace(meter.steps),
+        exceeded: EXCEEDED.replace(meter.exceeded),
+    };
+    NEXT_CHECK.set(next_check(meter.budget, meter.steps));
+    outer
+}
+
+fn next_check(budget: Budget, steps: u64) -> u64 {
+    let clock = match budget.deadline {
+        Some(_) => steps + CLOCK_EVERY,
+        None => u64::MAX,
+    };
+    clock.min(budget.max_steps.map_or(u64::MAX, |max_steps| max_steps.saturating_add(1)))
+}
+
+// called before every step, `at` is only asked for when the budget ran out
+#[inline]
+pub fn step(at: impl FnOnce() -> Span) -> Result<(), String> {
+    let steps = STEPS.get() + 1;
+    STEPS.set(steps);
+    if steps < NEXT_CHECK.get() { return Ok(()); }
+    check(steps, at())
+}
+
+fn check(steps: u64, span: Span) -> Result<(), String> {
+    let budget = BUDGET.get();
+    let elapsed = STARTED.get().map_or(Duration::ZERO, |started| started.elapsed());
+
+    let reason = if budget.max_steps.is_some_and(|max_steps| steps > max_steps) {
+        format!("the limit of {} steps", budget.max_steps.unwrap_or_default())
+    } else if budget.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        "the deadline".to_string()
+    } else {
+        NEXT_CHECK.set(next_check(budget, steps));
+        return Ok(());
+    };
+
+    // the step that went over doesnt get to run
+    let steps = steps - 1;
+    EXCEEDED.set(Some(Exceeded { steps, elapsed, span }));
+    Err(format!("Budget exceeded, hit {reason} after {steps} steps in {elapsed:.2?} {span}"))
+}
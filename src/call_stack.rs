@@ -0,0 +1,141 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use crate::scanner::Span;
+
+// the raz functions being run right now, kept apart from the environments because those follow
+// the scopes in the source while this follows who called who
+struct CallStack {
+    frames: Vec<Frame>,
+    // the ones of the interpreter running right now
+    limits: Limits,
+    // about where on the rust stack that interpreter started, calls are measured from here
+    stack_start: Option<usize>,
+    // the stack as it was where an error first came out of a call, `take_traceback` hands it over
+    traceback: Option<String>,
+}
+
+struct Frame {
+    name: Rc<str>,
+    call_site: Span,
+}
+
+// how deep raz calls can go before it's a raz stack overflow instead of the rust stack running out.
+// every interpreter has its own, `enter` makes them the ones checked while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_depth: usize,
+    // bytes of the rust stack calls can use, has to be less than the thread has. what a call takes
+    // depends on the build and on how many blocks and expressions it's nested in, a debug build
+    // takes many times what a release one does, so this is what actually keeps it from crashing
+    pub stack_size: usize,
+}
+
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+// fits on any thread rust starts (2 MiB by default), the runner has a much bigger one
+pub const DEFAULT_STACK_SIZE: usize = 1024 * 1024;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_depth: DEFAULT_MAX_DEPTH, stack_size: DEFAULT_STACK_SIZE }
+    }
+}
+
+// what was being checked before `enter`, `leave` puts it back
+pub struct Entered {
+    limits: Limits,
+    stack_start: Option<usize>,
+}
+
+// a traceback longer than this only shows both ends of the stack
+const TRACEBACK_INNERMOST: usize = 10;
+const TRACEBACK_OUTERMOST: usize = 5;
+
+thread_local! {
+    static CALL_STACK: RefCell<CallStack> = const {
+        RefCell::new(CallStack {
+            frames: vec![],
+            limits: Limits { max_depth: DEFAULT_MAX_DEPTH, stack_size: DEFAULT_STACK_SIZE },
+            stack_start: None,
+            traceback: None,
+        })
+    };
+}
+
+// about where the rust stack is at right now
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+pub fn enter(limits: Limits) -> Entered {
+    let stack_start = Some(stack_address());
+    CALL_STACK.with_borrow_mut(|stack| Entered {
+        limits: std::mem::replace(&mut stack.limits, limits),
+        stack_start: std::mem::replace(&mut stack.stack_start, stack_start),
+    })
+}
+
+pub fn leave(entered: Entered) {
+    CALL_STACK.with_borrow_mut(|stack| {
+        stack.limits = entered.limits;
+        stack.stack_start = entered.stack_start;
+    });
+}
+
+pub fn push(name: &Rc<str>, call_site: Span) -> Result<(), String> {
+    let (limits, stack_start) = CALL_STACK.with_borrow(|stack| (stack.limits, stack.stack_start));
+    let depth = depth();
+    if depth >= limits.max_depth {
+        record_error();
+        return Err(format!("Stack overflow, calling {name} goes deeper than {} calls {call_site}", limits.max_depth));
+    }
+    // the stack grows down on everything rust runs on, but the distance is all that matters
+    if stack_start.is_some_and(|start| start.abs_diff(stack_address()) > limits.stack_size) {
+        record_error();
+        return Err(format!(
+            "Stack overflow, calling {name} {depth} calls deep goes over the {} bytes of stack calls can use {call_site}",
+            limits.stack_size
+        ));
+    }
+    CALL_STACK.with_borrow_mut(|stack| stack.frames.push(Frame { name: name.clone(), call_site }));
+    Ok(())
+}
+
+pub fn depth() -> usize {
+    CALL_STACK.with_borrow(|stack| stack.frames.len())
+}
+
+pub fn pop() {
+    CALL_STACK.with_borrow_mut(|stack| { stack.frames.pop(); });
+}
+
+// called on the way out of a failed call, only the innermost one gets to keep its stack
+pub fn record_error() {
+    CALL_STACK.with_borrow_mut(|stack| {
+        if stack.traceback.is_some() { return; }
+        stack.traceback = Some(traceback(
+            stack.frames.iter().rev().map(|frame| (&*frame.name, frame.call_site))
+        ));
+    });
+}
+
+// the calls given innermost first, the vm formats its own frames with this too
+pub fn traceback<'a>(frames: impl Iterator<Item = (&'a str, Span)>) -> String {
+    let mut lines: Vec<String> = frames
+        .map(|(name, call_site)| format!("    in {name}, called at {call_site}"))
+        .collect();
+    if lines.len() > TRACEBACK_INNERMOST + TRACEBACK_OUTERMOST {
+        let hidden = lines.len() - TRACEBACK_INNERMOST - TRACEBACK_OUTERMOST;
+        lines.splice(
+            TRACEBACK_INNERMOST..lines.len() - TRACEBACK_OUTERMOST,
+            [format!("    ... {hidden} more calls")],
+        );
+    }
+    lines.join("\n")
+}
+
+pub fn take_traceback() -> Option<String> {
+    CALL_STACK.with_borrow_mut(|stack| stack.traceback.take())
+}
@@ -53,11 +53,12 @@ impl Compiler {
         let mut natives = Environment::new();
 
         natives.define(
-            "clock".to_string(),
+            "clock".into(),
             LiteralValue::Callable {
-                name: "clock".to_string(),
+                name: "clock".into(),
                 arity: 0,
-                func: Rc::new(clock_impl)
+                func: Rc::new(clock_impl),
+                closure: None,
             });
 
         Self {
@@ -75,7 +76,7 @@ impl Compiler {
             let env = self.environment.clone();
             // only does like print statements I will do the rest tomorrow or later
             match stmt {
-                Stmt::Print { expression } => {
+                Stmt::Print { expression, span: _ } => {
                     let print_contents = expression.evaluate(env).unwrap();
 
                     let string_label = format!("s{string_counter}");
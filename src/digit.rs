@@ -1,8 +1,11 @@
 
 use std::fmt;
+use std::num::{IntErrorKind, ParseIntError};
 use std::ops::{
     Add, Sub, Div, Mul,
-    Neg, Rem
+    Neg, Rem,
+    BitAnd, BitOr, BitXor,
+    Not, Shl, Shr
 };
 
 use std::cmp::{
@@ -11,8 +14,7 @@ use std::cmp::{
     Ordering
 };
 
-// TODO handle all variants + check for overflowing
-
+#[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy)]
 pub enum DigitType { // might capitalise these types later
     u8(u8), u16(u16), u32(u32), u64(u64),
@@ -20,52 +22,223 @@ pub enum DigitType { // might capitalise these types later
                       f32(f32), f64(f64)
 }
 
-impl DigitType {
-    pub fn from_string(s: String) -> Self {
-        if let Ok(v) = s.parse::<u8>() { Self::u8(v) }
-        else if let Ok(v) = s.parse::<u16>() { Self::u16(v) }
-        else if let Ok(v) = s.parse::<u32>() { Self::u32(v) }
-        else if let Ok(v) = s.parse::<u64>() { Self::u64(v) }
+// both operands brought to a common representation before doing any math
+enum Promoted {
+    // values, width in bits and signedness of the wider operand
+    Int(i128, i128, u32, bool),
+    F32(f32, f32),
+    F64(f64, f64),
+}
 
-        else if let Ok(v) = s.parse::<i32>() { Self::i32(v) }
-        else if let Ok(v) = s.parse::<f64>() { Self::f64(v) }
-        else { panic!("DigitType from_string error")  }
+fn int_error(literal: &str, ty: &str, err: ParseIntError) -> String {
+    match err.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow =>
+            format!("Number literal {literal} is out of range for {ty}"),
+        IntErrorKind::Empty => format!("Number literal {literal} has no digits"),
+        _ => format!("Number literal {literal} has an invalid digit for its base"),
     }
+}
 
-    // this section has to be todod better later i dont like this and cannot think properly
-    pub fn pow(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => {
-                let e = u32::pow(a as u32, b as u32);
-                DigitType::u32(e)
+fn float_error(literal: &str, ty: &str) -> String {
+    format!("Number literal {literal} is not a valid {ty}")
+}
+
+impl DigitType {
+    // parses a number literal the way the scanner reads it:
+    // `1_000_000`, `0xFF`, `0b1010`, `0o17`, `1.5e-3` and type suffixes like `5u8` or `2.0f32`
+    // without a suffix integers get the smallest unsigned type they fit in and floats are f64
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        let (radix, body) = match s.get(..2) {
+            Some("0x") => (16, &s[2..]),
+            Some("0b") => (2, &s[2..]),
+            Some("0o") => (8, &s[2..]),
+            _ => (10, s),
+        };
+
+        // the suffix starts at the first thing that cant be part of the number,
+        // `f` is a hex digit so `0xf32` is just a hex number
+        let is_number_char = |c: char| c == '_' || c.is_digit(radix)
+            || (radix == 10 && matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+        let (digits, suffix) = match body.find(|c: char| !is_number_char(c)) {
+            Some(at) => (&body[..at], Some(&body[at..])),
+            None => (body, None),
+        };
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            return Err(format!("Number literal {s} has no digits"));
+        }
+        let is_float = radix == 10 && digits.contains(['.', 'e', 'E']);
+
+        match suffix {
+            None if is_float => Self::parse_f64(s, &digits),
+            None => {
+                let value = u64::from_str_radix(&digits, radix)
+                    .map_err(|err| int_error(s, "u64", err))?;
+                Ok(Self::fit_int(value as i128, 8, false)
+                    .expect("u64 always fits in the integer variants"))
             },
-            (DigitType::u8(a), DigitType::i8(b)) => {
-                let e = u32::pow(a as u32, b as u32);
-                DigitType::u32(e)
+            Some("f32" | "f64") if radix != 10 => Err(format!("Number literal {s} cannot have a float suffix")),
+            Some("f32") => {
+                let value = digits.parse::<f32>().map_err(|_| float_error(s, "f32"))?;
+                if value.is_finite() { Ok(Self::f32(value)) }
+                else { Err(format!("Number literal {s} is out of range for f32")) }
             },
-            _ => panic!("Incompatible types for exponentiation"),
+            Some("f64") => Self::parse_f64(s, &digits),
+            Some(ty) if is_float => Err(format!("Float literal {s} cannot have the integer suffix {ty}")),
+            Some("u8") => u8::from_str_radix(&digits, radix).map(Self::u8).map_err(|err| int_error(s, "u8", err)),
+            Some("u16") => u16::from_str_radix(&digits, radix).map(Self::u16).map_err(|err| int_error(s, "u16", err)),
+            Some("u32") => u32::from_str_radix(&digits, radix).map(Self::u32).map_err(|err| int_error(s, "u32", err)),
+            Some("u64") => u64::from_str_radix(&digits, radix).map(Self::u64).map_err(|err| int_error(s, "u64", err)),
+            Some("i8") => i8::from_str_radix(&digits, radix).map(Self::i8).map_err(|err| int_error(s, "i8", err)),
+            Some("i16") => i16::from_str_radix(&digits, radix).map(Self::i16).map_err(|err| int_error(s, "i16", err)),
+            Some("i32") => i32::from_str_radix(&digits, radix).map(Self::i32).map_err(|err| int_error(s, "i32", err)),
+            Some("i64") => i64::from_str_radix(&digits, radix).map(Self::i64).map_err(|err| int_error(s, "i64", err)),
+            Some(ty) if ty.starts_with(|c: char| c.is_ascii_digit()) =>
+                Err(format!("Number literal {s} has an invalid digit for its base")),
+            Some(ty) => Err(format!("Invalid suffix {ty} on number literal {s}")),
+        }
+    }
+
+    fn parse_f64(literal: &str, digits: &str) -> Result<Self, String> {
+        let value = digits.parse::<f64>().map_err(|_| float_error(literal, "f64"))?;
+        if value.is_finite() { Ok(Self::f64(value)) }
+        else { Err(format!("Number literal {literal} is out of range for f64")) }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::f32(_) | Self::f64(_))
+    }
+
+    // (value, width in bits, signed) for the integer variants
+    fn int_parts(self) -> Option<(i128, u32, bool)> {
+        match self {
+            Self::u8(v) => Some((v as i128, 8, false)),
+            Self::u16(v) => Some((v as i128, 16, false)),
+            Self::u32(v) => Some((v as i128, 32, false)),
+            Self::u64(v) => Some((v as i128, 64, false)),
+            Self::i8(v) => Some((v as i128, 8, true)),
+            Self::i16(v) => Some((v as i128, 16, true)),
+            Self::i32(v) => Some((v as i128, 32, true)),
+            Self::i64(v) => Some((v as i128, 64, true)),
+            Self::f32(_) | Self::f64(_) => None,
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Self::u8(v) => v as f64,
+            Self::u16(v) => v as f64,
+            Self::u32(v) => v as f64,
+            Self::u64(v) => v as f64,
+            Self::i8(v) => v as f64,
+            Self::i16(v) => v as f64,
+            Self::i32(v) => v as f64,
+            Self::i64(v) => v as f64,
+            Self::f32(v) => v as f64,
+            Self::f64(v) => v,
+        }
+    }
+
+    fn int_of(value: i128, bits: u32, signed: bool) -> Option<Self> {
+        match (bits, signed) {
+            (8, false) => u8::try_from(value).ok().map(Self::u8),
+            (16, false) => u16::try_from(value).ok().map(Self::u16),
+            (32, false) => u32::try_from(value).ok().map(Self::u32),
+            (64, false) => u64::try_from(value).ok().map(Self::u64),
+            (8, true) => i8::try_from(value).ok().map(Self::i8),
+            (16, true) => i16::try_from(value).ok().map(Self::i16),
+            (32, true) => i32::try_from(value).ok().map(Self::i32),
+            (64, true) => i64::try_from(value).ok().map(Self::i64),
+            _ => None,
+        }
+    }
+
+    // puts an integer result back into a variant, starting at the operands width
+    // and growing when it doesnt fit (u8 + u8 that overflows becomes a u16 and so on),
+    // unsigned results that went below zero carry on as signed. a signed one past i64
+    // that isnt negative still fits in a u64 (u64::MAX + 0i8, or i64::MAX + 1)
+    fn fit_int(value: i128, bits: u32, signed: bool) -> Result<Self, String> {
+        let signed = signed || value < 0;
+        let mut bits = bits;
+        while bits <= 64 {
+            if let Some(digit) = Self::int_of(value, bits, signed) {
+                return Ok(digit);
+            }
+            bits *= 2;
+        }
+        match u64::try_from(value) {
+            Ok(value) => Ok(Self::u64(value)),
+            Err(_) => Err(format!("Integer overflow: {value} does not fit in a 64-bit integer")),
+        }
+    }
+
+    // both operands for the bitwise operators, which only make sense on integers
+    fn promote_bits(self, rhs: Self, op: &str) -> Result<(i128, i128, u32, bool), String> {
+        match self.promote(rhs) {
+            Promoted::Int(a, b, bits, signed) => Ok((a, b, bits, signed)),
+            _ => Err(format!("Bitwise {op} needs integer operands, got {self} and {rhs}")),
+        }
+    }
+
+    // the shift amount has to be a small non negative integer
+    fn shift_parts(self, rhs: Self, op: &str) -> Result<(i128, u32, bool, u32), String> {
+        let Some((value, bits, signed)) = self.int_parts() else {
+            return Err(format!("Bitwise {op} needs integer operands, got {self} and {rhs}"));
+        };
+        match rhs.int_parts() {
+            Some((amount, _, _)) if (0..64).contains(&amount) => Ok((value, bits, signed, amount as u32)),
+            Some(_) => Err(format!("Shift amount {rhs} is out of range 0..64")),
+            None => Err(format!("Bitwise {op} needs integer operands, got {self} and {rhs}")),
         }
     }
 
-    pub fn root(self, rhs: Self) -> Self {
+    fn promote(self, rhs: Self) -> Promoted {
         match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => {
-                let e = (1f32 / a as f32);
-                DigitType::f32(f32::powf(b as f32, e))
+            (Self::f64(_), _) | (_, Self::f64(_)) => Promoted::F64(self.as_f64(), rhs.as_f64()),
+            (Self::f32(_), _) | (_, Self::f32(_)) => Promoted::F32(self.as_f64() as f32, rhs.as_f64() as f32),
+            _ => {
+                let (a, a_bits, a_signed) = self.int_parts().expect("floats handled above");
+                let (b, b_bits, b_signed) = rhs.int_parts().expect("floats handled above");
+                Promoted::Int(a, b, a_bits.max(b_bits), a_signed || b_signed)
             },
-            (DigitType::u8(a), DigitType::i8(b)) => {
-                let e = (1f32 / b as f32);
-                DigitType::f32(f32::powf(a as f32, e))
+        }
+    }
+
+    pub fn pow(self, rhs: Self) -> Result<Self, String> {
+        match self.promote(rhs) {
+            Promoted::Int(a, b, bits, signed) if b >= 0 => {
+                let result = u32::try_from(b).ok().and_then(|b| a.checked_pow(b));
+                match result {
+                    Some(result) => Self::fit_int(result, bits, signed),
+                    None => Err(format!("Integer overflow: {a} ^ {b}")),
+                }
             },
-            (a,b) => panic!("Incompatible types for root for: {a:?} AND {b:?}"),
+            // negative exponents cant stay integers
+            Promoted::Int(a, b, _, _) => Ok(Self::f64((a as f64).powf(b as f64))),
+            Promoted::F32(a, b) => Ok(Self::f32(a.powf(b))),
+            Promoted::F64(a, b) => Ok(Self::f64(a.powf(b))),
         }
     }
 
-    pub fn sqrt(self) -> Self {
-        match self {
-            DigitType::u8(a) => DigitType::u8(a.isqrt()),
-            _ => panic!("Incompatible types for square rooting"),
+    // `degree`th root of self, always a float
+    pub fn root(self, degree: Self) -> Result<Self, String> {
+        let x = self.as_f64();
+        let n = degree.as_f64();
+        if n == 0.0 {
+            return Err("Cannot take the 0th root".to_string());
+        }
+
+        let odd_degree = n.fract() == 0.0 && n % 2.0 != 0.0;
+        if x < 0.0 && !odd_degree {
+            return Err(format!("Cannot take root {n} of negative number {x}"));
         }
+
+        let magnitude = x.abs().powf(1.0 / n);
+        Ok(Self::f64(if x < 0.0 { -magnitude } else { magnitude }))
+    }
+
+    pub fn sqrt(self) -> Result<Self, String> {
+        self.root(Self::u8(2))
     }
 }
 
@@ -85,163 +258,232 @@ impl fmt::Display for DigitType {
             // float
             Self::f32(v) => write!(f, "{v}"),
             Self::f64(v) => write!(f, "{v}"),
-            v => panic!("Incomplete type writing/printing for {v}")
         }
     }
 }
 
-// swap the below to something similar like this
-// impl<'a, 'b> Add<&'b Vector> for &'a Vector {
-//     type Output = Vector;
-
-//     fn add(self, other: &'b Vector) -> Vector {
-//         Vector {
-//             x: self.x + other.x,
-//             y: self.y + other.y,
-//         }
-//     }
-// }
+// the operators give back a Result so overflow, division by zero and
+// the like turn into raz errors instead of panicking the interpreter
 
 impl Add for DigitType {
-    type Output = Self;
-    fn add(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => {
-                let c = a.checked_add(b);
-                if let Some(res) = c {
-                    DigitType::u8(res)
-                } else {
-                    DigitType::u16(a as u16 + b as u16)
-                }
-            },
-            (DigitType::u16(a), DigitType::u16(b)) => {
-                let c = a.checked_add(b);
-                if let Some(res) = c {
-                    DigitType::u16(res)
-                } else {
-                    DigitType::u32(a as u32 + b as u32)
-                }
-            },
-            (DigitType::u32(a), DigitType::u32(b)) => DigitType::u32(a + b),
-            (DigitType::u64(a), DigitType::u64(b)) => DigitType::u64(a + b),
-
-            (DigitType::u16(a), DigitType::u8(b)) => {
-                let c = a.checked_add(b as u16);
-                if let Some(res) = c {
-                    DigitType::u16(res)
-                } else {
-                    DigitType::u32(a as u32 + b as u32)
-                }
-            },
-            (DigitType::u32(a), DigitType::u8(b)) => DigitType::u32(a + b as u32),
-
-            (DigitType::i32(a), DigitType::i32(b)) => DigitType::i32(a + b),
-            (DigitType::i8(a), DigitType::i32(b)) => DigitType::i32(a as i32 + b),
-            (DigitType::i32(a), DigitType::i8(b)) => DigitType::i32(a + b as i32),
-            _ => panic!("Incompatible types for addition"),
+    type Output = Result<Self, String>;
+    fn add(self, rhs: Self) -> Self::Output {
+        match self.promote(rhs) {
+            Promoted::Int(a, b, bits, signed) => Self::fit_int(a + b, bits, signed),
+            Promoted::F32(a, b) => Ok(Self::f32(a + b)),
+            Promoted::F64(a, b) => Ok(Self::f64(a + b)),
         }
     }
 }
 
 impl Sub for DigitType {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => DigitType::u8(a - b),
-            (DigitType::i32(a), DigitType::i32(b)) => DigitType::i32(a - b),
-
-            (DigitType::f64(a), DigitType::f64(b)) => DigitType::f64(a - b),
-            (a,b) => panic!("Incompatible types for subtraction: {a} AND {b}"),
+    type Output = Result<Self, String>;
+    fn sub(self, rhs: Self) -> Self::Output {
+        match self.promote(rhs) {
+            Promoted::Int(a, b, bits, signed) => Self::fit_int(a - b, bits, signed),
+            Promoted::F32(a, b) => Ok(Self::f32(a - b)),
+            Promoted::F64(a, b) => Ok(Self::f64(a - b)),
         }
     }
 }
 
 impl Mul for DigitType {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => DigitType::u8(a * b),
-            (DigitType::i32(a), DigitType::i32(b)) => DigitType::i32(a * b),
-            _ => panic!("Incompatible types for multiplication"),
+    type Output = Result<Self, String>;
+    fn mul(self, rhs: Self) -> Self::Output {
+        match self.promote(rhs) {
+            Promoted::Int(a, b, bits, signed) => match a.checked_mul(b) {
+                Some(result) => Self::fit_int(result, bits, signed),
+                None => Err(format!("Integer overflow: {a} * {b}")),
+            },
+            Promoted::F32(a, b) => Ok(Self::f32(a * b)),
+            Promoted::F64(a, b) => Ok(Self::f64(a * b)),
         }
     }
 }
 
 impl Div for DigitType {
-    type Output = Self;
-    fn div(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => DigitType::u8(a / b),
-            (DigitType::i32(a), DigitType::i32(b)) => DigitType::i32(a / b),
-            _ => panic!("Incompatible types for division"),
+    type Output = Result<Self, String>;
+    fn div(self, rhs: Self) -> Self::Output {
+        match self.promote(rhs) {
+            Promoted::Int(_, 0, _, _) => Err("Division by zero".to_string()),
+            Promoted::Int(a, b, bits, signed) => Self::fit_int(a / b, bits, signed),
+            Promoted::F32(a, b) => Ok(Self::f32(a / b)),
+            Promoted::F64(a, b) => Ok(Self::f64(a / b)),
         }
     }
 }
 
 impl Neg for DigitType {
-    type Output = Self;
-    fn neg(self) -> Self {
+    type Output = Result<Self, String>;
+    fn neg(self) -> Self::Output {
         match self {
-            //DigitType::u8(x) => DigitType::u8(-x),
-            DigitType::i32(x) => DigitType::i32(-x),
-            _ => panic!("Incompatible types for negation"),
+            Self::f32(x) => Ok(Self::f32(-x)),
+            Self::f64(x) => Ok(Self::f64(-x)),
+            int => {
+                let (x, bits, _) = int.int_parts().expect("floats handled above");
+                Self::fit_int(-x, bits, true)
+            },
         }
     }
 }
 
 impl Rem for DigitType {
-    type Output = Self;
-    fn rem(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (DigitType::u8(a), DigitType::u8(b)) => DigitType::u8(a % b),
-            (DigitType::i32(a), DigitType::i32(b)) => DigitType::i32(a % b),
-            _ => panic!("Incompatible types for remainders/modulo"),
+    type Output = Result<Self, String>;
+    fn rem(self, rhs: Self) -> Self::Output {
+        match self.promote(rhs) {
+            Promoted::Int(_, 0, _, _) => Err("Remainder by zero".to_string()),
+            Promoted::Int(a, b, bits, signed) => Self::fit_int(a % b, bits, signed),
+            Promoted::F32(a, b) => Ok(Self::f32(a % b)),
+            Promoted::F64(a, b) => Ok(Self::f64(a % b)),
+        }
+    }
+}
+
+// bitwise ops work on the twos complement value so negative numbers behave like in rust,
+// `&`, `|` and `xor` use the wider operand type and shifting left grows the type like `+` does
+
+impl BitAnd for DigitType {
+    type Output = Result<Self, String>;
+    fn bitand(self, rhs: Self) -> Self::Output {
+        let (a, b, bits, signed) = self.promote_bits(rhs, "&")?;
+        Self::fit_int(a & b, bits, signed)
+    }
+}
+
+impl BitOr for DigitType {
+    type Output = Result<Self, String>;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        let (a, b, bits, signed) = self.promote_bits(rhs, "|")?;
+        Self::fit_int(a | b, bits, signed)
+    }
+}
+
+impl BitXor for DigitType {
+    type Output = Result<Self, String>;
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let (a, b, bits, signed) = self.promote_bits(rhs, "xor")?;
+        Self::fit_int(a ^ b, bits, signed)
+    }
+}
+
+impl Shl for DigitType {
+    type Output = Result<Self, String>;
+    fn shl(self, rhs: Self) -> Self::Output {
+        let (value, bits, signed, amount) = self.shift_parts(rhs, "<<")?;
+        match value.checked_mul(1i128 << amount) {
+            Some(result) => Self::fit_int(result, bits, signed),
+            None => Err(format!("Integer overflow: {self} << {rhs}")),
         }
     }
 }
 
+impl Shr for DigitType {
+    type Output = Result<Self, String>;
+    fn shr(self, rhs: Self) -> Self::Output {
+        let (value, bits, signed, amount) = self.shift_parts(rhs, ">>")?;
+        Self::fit_int(value >> amount, bits, signed)
+    }
+}
+
+impl Not for DigitType {
+    type Output = Result<Self, String>;
+    fn not(self) -> Self::Output {
+        let Some((value, bits, signed)) = self.int_parts() else {
+            return Err(format!("Bitwise ~ needs an integer operand, got {self}"));
+        };
+        // unsigned values flip only their own bits, `~0u8` is 255
+        let flipped = if signed { !value } else { !value & ((1i128 << bits) - 1) };
+        Self::fit_int(flipped, bits, signed)
+    }
+}
+
 impl PartialEq for DigitType {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (DigitType::u8(a), DigitType::u8(b)) => a == b,
-            (DigitType::i32(a), DigitType::i32(b)) => a == b,
-            _ => panic!("Incompatible types for equality"),
-        }
+        self.partial_cmp(other) == Some(Ordering::Equal)
     }
 }
 
+// different variants compare by value, `5u8 == 5i32` and `1 < 2.5`
 impl PartialOrd for DigitType {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        None
+        match self.promote(*other) {
+            Promoted::Int(a, b, _, _) => Some(a.cmp(&b)),
+            Promoted::F32(a, b) => a.partial_cmp(&b),
+            Promoted::F64(a, b) => a.partial_cmp(&b),
+        }
     }
+}
 
-    fn le(&self, other: &Self) -> bool {
-        match (self, other) {
-            (DigitType::u8(a), DigitType::u8(b)) => a <= b,
-            (DigitType::u16(a), DigitType::u16(b)) => a <= b,
-            (DigitType::u32(a), DigitType::u32(b)) => a <= b,
-            (DigitType::u64(a), DigitType::u64(b)) => a <= b,
-
-            (DigitType::u8(a), DigitType::u16(b)) => &(*a as u16) <= b,
-            (DigitType::u16(a), DigitType::u8(b)) => a <= &(*b as u16),
-            (DigitType::u8(a), DigitType::u32(b)) => &(*a as u32) <= b,
-            (DigitType::u32(a), DigitType::u8(b)) => a <= &(*b as u32),
+#[cfg(test)]
+mod tests {
+    use super::DigitType::{self, *};
 
-            (DigitType::u16(a), DigitType::u32(b)) => &(*a as u32) <= b,
-            (DigitType::u32(a), DigitType::u16(b)) => a <= &(*b as u32),
+    fn ok(result: Result<DigitType, String>) -> DigitType {
+        result.unwrap_or_else(|msg| panic!("expected a number, got the error {msg}"))
+    }
 
-            (DigitType::u8(a), DigitType::u32(b)) => &(*a as u32) <= b,
-            (DigitType::u32(a), DigitType::u8(b)) => a <= &(*b as u32),
+    #[test]
+    fn overflow_grows_into_the_next_width() {
+        assert!(matches!(ok(u8(255) + u8(1)), u16(256)));
+        assert!(matches!(ok(u16(65535) + u8(1)), u32(65536)));
+        assert!(matches!(ok(u32(u32::MAX) * u8(2)), u64(8589934590)));
+        assert!(matches!(ok(i8(-128) - i8(1)), i16(-129)));
+        assert!(matches!(ok(u8(16).pow(u8(2))), u16(256)));
+    }
 
+    #[test]
+    fn unsigned_going_below_zero_turns_signed() {
+        assert!(matches!(ok(u8(0) - u8(1)), i8(-1)));
+        assert!(matches!(ok(u8(0) - u8(200)), i16(-200)));
+        assert!(matches!(ok(-u8(200)), i16(-200)));
+        assert!(matches!(ok(-u64(5)), i64(-5)));
+    }
 
-            (DigitType::u32(a), DigitType::u32(b)) => a <= b,
+    #[test]
+    fn past_64_bits_is_an_error() {
+        assert!((u64(u64::MAX) + u8(1)).is_err());
+        assert!((i64(i64::MAX) * i64(4)).is_err());
+        assert!((i64(i64::MIN) - i64(1)).is_err());
+        assert!((u64(u64::MAX) * u64(u64::MAX)).is_err());
+        assert!(u8(2).pow(u8(200)).is_err());
+    }
 
+    #[test]
+    fn signed_results_past_i64_fall_back_to_u64() {
+        assert!(matches!(ok(u64(u64::MAX) + i8(0)), u64(u64::MAX)));
+        assert!(matches!(ok(u64(u64::MAX) - i8(1)), u64(18446744073709551614)));
+        assert!(matches!(ok(i8(1) + u64(9223372036854775807)), u64(9223372036854775808)));
+        assert!(matches!(ok(u64(u64::MAX) + i8(-1)), u64(18446744073709551614)));
+        assert!(matches!(ok(i64(i64::MAX) + i64(1)), u64(9223372036854775808)));
+        assert!(matches!(ok(i64(i64::MIN) / i64(-1)), u64(9223372036854775808)));
+        assert!(matches!(ok(-i64(i64::MIN)), u64(9223372036854775808)));
+        assert!((u64(u64::MAX) + i8(1)).is_err());
+        assert!(matches!(ok(i8(-1) * u64(5)), i64(-5)));
+    }
 
-            (DigitType::u8(a), DigitType::u8(b)) => a <= b,
+    #[test]
+    fn zero_divisors_are_errors() {
+        assert_eq!(u8(1) / u8(0), Err("Division by zero".to_string()));
+        assert_eq!(i32(1) % u8(0), Err("Remainder by zero".to_string()));
+        assert!(matches!(ok(f64(1.0) / u8(0)), f64(v) if v.is_infinite()));
+    }
 
-            (DigitType::i32(a), DigitType::i32(b)) => a <= b,
+    #[test]
+    fn mixed_operands_take_the_wider_type() {
+        assert!(matches!(ok(u8(5) + i16(300)), i16(305)));
+        assert!(matches!(ok(u16(5) + i8(1)), i16(6)));
+        assert!(matches!(ok(i64(1) + u8(1)), i64(2)));
+        assert!(matches!(ok(u8(1) + f32(0.5)), f32(1.5)));
+        assert!(matches!(ok(f32(1.0) + f64(0.5)), f64(1.5)));
+        assert!(matches!(ok(u8(2).pow(i8(-1))), f64(0.5)));
+    }
 
-            (a, b) => panic!("Incompatible types for ord: {a:?} AND {b:?}"),
-        }
+    #[test]
+    fn different_variants_compare_by_value() {
+        assert_eq!(u8(5), i32(5));
+        assert_eq!(u64(1), f64(1.0));
+        assert!(u8(1) < f64(2.5));
+        assert!(i8(-1) < u64(0));
+        assert!(f64(f64::NAN) != f64(f64::NAN));
     }
 }
@@ -1,56 +1,176 @@
-
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 use std::collections::HashMap;
 
 use crate::literals::LiteralValue;
+use crate::memory::{self, Charge, Collect};
+
+// where the resolver found a local variable: how many environments up, and which value in there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+// the environment a function was declared in. the copy of the function that's stored in that same
+// environment only holds on to it weakly, otherwise each would keep the other alive and a block or
+// call that declares a function would never be dropped. reading it back out makes it strong again
+#[derive(Clone)]
+pub enum Captured {
+    Strong(Rc<RefCell<Environment>>),
+    Weak(Weak<RefCell<Environment>>),
+}
+
+impl Captured {
+    pub fn environment(self: &Self) -> Rc<RefCell<Environment>> {
+        match self {
+            Captured::Strong(env) => env.clone(),
+            Captured::Weak(env) => env.upgrade().expect("weak closures are only read from the environment they point at"),
+        }
+    }
+}
 
 pub struct Environment {
-    values: HashMap<String, LiteralValue>,
+    // locals in the order they got defined, the same order the resolver gave out their slots in
+    values: Vec<LiteralValue>,
+    // only the outermost environment has these, globals arent resolved so they go by name
+    globals: HashMap<Rc<str>, LiteralValue>,
     pub enclosing: Option<Rc<RefCell<Environment>>>,
+    // counted against the memory limit, one value's worth for every variable too
+    charge: Charge,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
-            values: HashMap::new(),
+            values: vec![],
+            globals: HashMap::new(),
             enclosing: None,
+            charge: Charge::new(std::mem::size_of::<Self>()),
         }
     }
 
-    pub fn define(self: &mut Self, name: String, value: LiteralValue) {
-        self.values.insert(name, value);
+    pub fn define(self: &mut Self, name: Rc<str>, value: LiteralValue) {
+        let value = self.store(value);
+        let is_new = match self.enclosing {
+            Some(_) => {
+                self.values.push(value);
+                true
+            },
+            None => self.globals.insert(name, value).is_none(),
+        };
+        if is_new { self.charge.grow(std::mem::size_of::<LiteralValue>()); }
     }
 
-    pub fn define_top_level(&mut self, name: String, value: LiteralValue) {
-        match &self.enclosing {
-            None => self.define(name, value),
-            Some(env) => env.borrow_mut().define_top_level(name, value),
+    pub fn get_at(self: &Self, slot: Slot) -> Option<LiteralValue> {
+        match (slot.depth, &self.enclosing) {
+            (0, _) => self.values.get(slot.index).map(Self::load),
+            (depth, Some(env)) => env.borrow().get_at(Slot { depth: depth - 1, ..slot }),
+            (_, None) => None,
         }
     }
 
-    pub fn get(self: &Self, name: &str) -> Option<LiteralValue> {
-        let value = self.values.get(name);
-
-        match (value, &self.enclosing) {
-            (Some(val), _) => Some(val.clone()),
-            (None, Some(env)) => env.borrow().get(name),
-            (None, None) => None
+    pub fn assign_at(self: &mut Self, slot: Slot, value: LiteralValue) -> bool {
+        if slot.depth == 0 {
+            let value = self.store(value);
+            return match self.values.get_mut(slot.index) {
+                Some(old_value) => {
+                    *old_value = value;
+                    true
+                },
+                None => false,
+            };
+        }
+        match (slot.depth, &mut self.enclosing) {
+            (depth, Some(env)) => env.borrow_mut().assign_at(Slot { depth: depth - 1, ..slot }, value),
+            (_, None) => false,
         }
     }
 
-    pub fn assign(&mut self, name: &str, value: LiteralValue) -> bool {
-        let old_value = self.values.get(name);
+    // anything the resolver didnt find in a local scope is a global
+    pub fn get_global(self: &Self, name: &str) -> Option<LiteralValue> {
+        match &self.enclosing {
+            Some(env) => env.borrow().get_global(name),
+            None => self.globals.get(name).map(Self::load),
+        }
+    }
 
-        match (old_value, &mut self.enclosing) {
-            (Some(_), _) => {
-                self.values.insert(name.to_string(), value);
+    pub fn assign_global(self: &mut Self, name: &str, value: LiteralValue) -> bool {
+        if let Some(env) = &self.enclosing {
+            return env.borrow_mut().assign_global(name, value);
+        }
+        let value = self.store(value);
+        match self.globals.get_mut(name) {
+            Some(old_value) => {
+                *old_value = value;
                 true
             },
-            (None, Some(env)) => 
-                (env.borrow_mut()).assign(name, value),
-            (None, None) => false
+            None => false,
         }
     }
 
+    // a function that closes over this environment is kept with a weak pointer back to it
+    fn store(self: &Self, value: LiteralValue) -> LiteralValue {
+        match value {
+            LiteralValue::Callable { name, arity, func, closure: Some(Captured::Strong(env)) }
+                if std::ptr::eq(env.as_ptr(), self) =>
+            {
+                let closure = Some(Captured::Weak(Rc::downgrade(&env)));
+                LiteralValue::Callable { name, arity, func, closure }
+            },
+            value => value,
+        }
+    }
+
+    // and it's strong again once it's read, it can be kept around after this environment is gone
+    fn load(value: &LiteralValue) -> LiteralValue {
+        match value {
+            LiteralValue::Callable { name, arity, func, closure: Some(closure @ Captured::Weak(_)) } => {
+                let closure = Some(Captured::Strong(closure.environment()));
+                LiteralValue::Callable { name: name.clone(), arity: *arity, func: func.clone(), closure }
+            },
+            value => value.clone(),
+        }
+    }
+}
+
+impl Environment {
+    // a block or call that's done while something still points at its environment, like a function or class
+    // declared in it. that something might be stored in the environment itself, `memory::collect` finds out
+    pub fn end_scope(env: &Rc<RefCell<Environment>>) {
+        if Rc::strong_count(env) > 1 {
+            let env: Weak<RefCell<Environment>> = Rc::downgrade(env);
+            memory::suspect(env);
+        }
+    }
+
+    // functions and classes declared at the top point back at the globals, emptying them once the program
+    // is done is what lets all of that get dropped (and stop counting towards the memory limit)
+    pub fn clear_globals(self: &mut Self) {
+        self.charge.shrink(self.globals.len() * std::mem::size_of::<LiteralValue>());
+        self.globals.clear();
+    }
+}
+
+impl Collect for RefCell<Environment> {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        let Ok(env) = self.try_borrow() else { return false };
+        for value in env.values.iter().chain(env.globals.values()) {
+            value.edges(found);
+        }
+        if let Some(enclosing) = &env.enclosing {
+            found.push(enclosing.clone());
+        }
+        true
+    }
+
+    fn clear(self: &Self) {
+        let Ok(mut env) = self.try_borrow_mut() else { return };
+        let values = std::mem::take(&mut env.values);
+        let globals = std::mem::take(&mut env.globals);
+        let enclosing = env.enclosing.take();
+        // they might drop other environments, this one cant be borrowed while that happens
+        drop(env);
+        drop((values, globals, enclosing));
+    }
 }
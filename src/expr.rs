@@ -4,10 +4,16 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use crate::environment::Environment;
+use crate::budget;
+use crate::call_stack;
+use crate::environment::{Captured, Environment, Slot};
 use crate::digit::*;
-use crate::literals::LiteralValue::{self, *};
-use crate::scanner::{Token, TokenType};
+use crate::literals::{bind, CallResult, RazClass, RazInstance, RazString, LiteralValue::{self, *}};
+use crate::memory;
+use crate::scanner::{Span, Token, TokenType};
+use crate::stmt::Stmt;
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+use crate::interpreter::Interpreter;
 
 // use raz::{
 //     digit::*,
@@ -19,35 +25,76 @@ use crate::scanner::{Token, TokenType};
 
 #[derive(Clone)] // do i want this for this enum?
 
+// every node keeps the span of source it was parsed from so errors can point at it
+// `slot` is filled in by the resolver, where the local variable lives, `None` for globals
 pub enum Expr {
     // simple variables `var var1 = 5`, name: "var1", value
     Assignment {
         name: Token,
-        value: Box<Expr>
+        value: Box<Expr>,
+        slot: Option<Slot>,
+        span: Span,
     },
     // operations like "5 - 10" or "17 >= 0"
     Binary {
         left: Box<Expr>,
         right: Box<Expr>,
         operator: Token,
+        span: Span,
     },
     Call {
         callee: Box<Expr>,
         paren: Token,
         arguments:Vec<Expr>,
+        span: Span,
     },
-    Grouping { expression: Box<Expr> },
-    Literal { value: LiteralValue },
+    // `object.name`, a field or a method of an instance
+    Get {
+        object: Box<Expr>,
+        name: Token,
+        span: Span,
+    },
+    Grouping { expression: Box<Expr>, span: Span },
+    // `func (a, b) { ... }`, the arrow form `func (a) => a` has a single return as its body
+    Lambda {
+        params: Rc<[Token]>,
+        body: Rc<[Stmt]>,
+        span: Span,
+    },
+    // "text {expr} text", the parts get stringified and joined
+    Interpolation { parts: Vec<Expr>, span: Span },
+    Literal { value: LiteralValue, span: Span },
     Logical {
         left: Box<Expr>,
         right: Box<Expr>,
         operator: Token,
+        span: Span,
     },
     Unary {
         operator: Token,
-        val: Box<Expr>
+        val: Box<Expr>,
+        span: Span,
+    },
+    // `object.name = value`, with `operator` for `object.name += value` so the object is evaluated once
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+        operator: Option<Token>,
+        span: Span,
+    },
+    // `super.method`
+    Super { keyword: Token, method: Token, slot: Option<Slot>, span: Span },
+    This { keyword: Token, slot: Option<Slot>, span: Span },
+    Variable { name: Token, slot: Option<Slot>, span: Span },
+    // `++x`, `x--`... stores the new value, prefix gives it back and postfix gives the old one
+    // the target is either a `Variable` or a `Get`
+    Update {
+        target: Box<Expr>,
+        operator: Token,
+        prefix: bool,
+        span: Span,
     },
-    Variable { name: Token },
 }
 
 impl std::fmt::Debug for Expr {
@@ -57,73 +104,134 @@ impl std::fmt::Debug for Expr {
 }
 
 impl Expr {
+    pub fn span(self: &Self) -> Span {
+        match self {
+            Self::Assignment { span, .. }
+            | Self::Binary { span, .. }
+            | Self::Call { span, .. }
+            | Self::Get { span, .. }
+            | Self::Grouping { span, .. }
+            | Self::Interpolation { span, .. }
+            | Self::Lambda { span, .. }
+            | Self::Literal { span, .. }
+            | Self::Logical { span, .. }
+            | Self::Unary { span, .. }
+            | Self::Set { span, .. }
+            | Self::Super { span, .. }
+            | Self::This { span, .. }
+            | Self::Variable { span, .. }
+            | Self::Update { span, .. } => *span,
+        }
+    }
+
     pub fn evaluate(self: &Self, environment: Rc<RefCell<Environment>>) -> Result<LiteralValue, String> {
+        budget::step(|| self.span())?;
         match self {
-            Self::Assignment { name, value } => {
+            Self::Assignment { name, value, slot, span: _ } => {
                 let new_value = (*value).evaluate(environment.clone())?;
-                let assign_success = environment.borrow_mut().assign(&name.lexeme, new_value.clone());
+                let assign_success = Self::assign_variable(&environment, &name.lexeme, *slot, new_value.clone());
 
                 if assign_success { Ok(new_value) }
-                else { Err(format!("Variable {:?} has not been declared", name.lexeme)) }
+                else { Err(format!("Variable {:?} has not been declared {}", name.lexeme, name.span())) }
             },
-            Self::Variable { name } => match environment.borrow().get(&name.lexeme) {
+            Self::Variable { name, slot, span } => match Self::lookup(&environment, &name.lexeme, *slot) {
                 Some(value) => Ok(value.clone()),
-                None => Err(format!("Variable {:?} has not been declared", name.lexeme)),
+                None => Err(format!("Variable {:?} has not been declared {}", name.lexeme, span)),
+            },
+            Self::Update { target, operator, prefix, span } => {
+                let step = match operator.token_type {
+                    TokenType::PlusPlus => TokenType::Plus,
+                    _ => TokenType::Minus,
+                };
+                let increment = |old_value: &LiteralValue| {
+                    Self::binary_op(old_value, &NumberValue(DigitType::u8(1)), step)
+                        .map_err(|msg| format!("{msg} {span}"))
+                };
+
+                let (old_value, new_value) = match target.as_ref() {
+                    Self::Get { object, name, span: _ } => {
+                        let instance = Self::instance_of(object, environment.clone())?;
+                        let old_value = Self::property(&instance, name)?;
+                        let new_value = increment(&old_value)?;
+                        instance.set(&name.lexeme, new_value.clone());
+                        (old_value, new_value)
+                    },
+                    Self::Variable { name, slot, span: _ } => {
+                        let old_value = target.evaluate(environment.clone())?;
+                        let new_value = increment(&old_value)?;
+                        Self::assign_variable(&environment, &name.lexeme, *slot, new_value.clone());
+                        (old_value, new_value)
+                    },
+                    _ => return Err(format!("Invalid target for '{}' {}", operator.lexeme, span)),
+                };
+
+                if *prefix { Ok(new_value) } else { Ok(old_value) }
+            },
+            Self::Get { object, name, span: _ } => {
+                let instance = Self::instance_of(object, environment)?;
+                Self::property(&instance, name)
+            },
+            Self::Set { object, name, value, operator, span } => {
+                let instance = Self::instance_of(object, environment.clone())?;
+                let mut value = value.evaluate(environment)?;
+                if let Some(operator) = operator {
+                    let old_value = Self::property(&instance, name)?;
+                    value = Self::binary_op(&old_value, &value, operator.token_type)
+                        .map_err(|msg| format!("{msg} {span}"))?;
+                }
+                instance.set(&name.lexeme, value.clone());
+                Ok(value)
+            },
+            Self::This { keyword, slot, span: _ } => match Self::lookup(&environment, "this", *slot) {
+                Some(value) => Ok(value),
+                None => Err(format!("Cant use 'this' outside of a method {}", keyword.span())),
+            },
+            Self::Super { keyword, method, slot, span: _ } => {
+                // `this` is defined right before `super` when binding the method
+                let superclass = Self::lookup(&environment, "super", *slot);
+                let instance = Self::lookup(&environment, "this", slot.map(|slot| Slot { index: 0, ..slot }));
+                match (superclass, instance) {
+                    (Some(Class(superclass)), Some(Instance(instance))) => match superclass.find_method(&method.lexeme) {
+                        Some((found, owner)) => Ok(bind(found, instance, &owner)),
+                        None => Err(format!("Superclass {} has no method {:?} {}", superclass.name, method.lexeme, method.span())),
+                    },
+                    _ => Err(format!("Cant use 'super' outside of a subclass method {}", keyword.span())),
+                }
             },
             Self::Call {
                 callee,
-                paren:_,
-                arguments
+                paren: _,
+                arguments,
+                span: _,
             } => {
                 let callable = (*callee).evaluate(environment.clone())?;
-                match callable {
-                    Callable { name, arity, func } => {
-                        if arguments.len() != arity {
-                            return Err(format!(
-                                "Callable {} expected {} arguments but got {}.",
-                                name, arity, arguments.len()
-                            ));
-                        }
-                        let mut arg_vals = vec![];
-                        for arg in arguments {
-                            let val = arg.evaluate(environment.clone())?;
-                            arg_vals.push(val);
-                        }
-
-                        Ok(func(environment.clone(), &arg_vals))
-                    },
-                    unkn => Err(format!("{} is not callable.", unkn.to_type())),
+                let arg_vals = Self::arguments(arguments, environment.clone())?;
+
+                Self::call(callable, arg_vals, environment, callee.span())
+            },
+            Self::Literal { value, span: _ } => Ok((*value).clone()),
+            #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+            Self::Lambda { params, body, span: _ } => Ok(Interpreter::function("anonymous".into(), params, body, Some(environment))),
+            #[cfg(not(all(feature = "interpreter", not(feature = "compiler"))))]
+            Self::Lambda { params: _, body: _, span } => Err(format!("Anonymous functions need the interpreter {span}")),
+            Self::Grouping { expression, span: _ } => expression.evaluate(environment),
+            Self::Interpolation { parts, span } => {
+                let mut result = String::new();
+                for part in parts {
+                    result.push_str(&part.evaluate(environment.clone())?.to_string());
                 }
+                RazString::new(result).map(StringValue).map_err(|msg| format!("{msg} {span}"))
             },
-            Self::Literal { value } => Ok((*value).clone()),
-            Self::Grouping { expression } => expression.evaluate(environment),
-            Self::Unary { operator, val } => {
+            Self::Unary { operator, val, span } => {
                 let val = val.evaluate(environment)?;
-                match (&val, operator.token_type) { // maybe dont give the reference to not derefence so much?
-                    // this will be an issue now
-                    (NumberValue(x), TokenType::Minus)      => Ok(NumberValue(-(*x))),
-                    (NumberValue(x), TokenType::Plus)       => Ok(NumberValue(*x)),
-                    (NumberValue(x), TokenType::MinusMinus) => Ok(NumberValue(*x-DigitType::i8(1))),
-                    (NumberValue(x), TokenType::PlusPlus)   => Ok(NumberValue(*x+DigitType::i8(1))),
-                    (NumberValue(x), TokenType::Root)       => Ok(NumberValue((*x).root(DigitType::i8(2)))), // square rooting
-                    // {
-                    //     let res = f64::sqrt(*x);
-                    //     Ok(NumberValue(DigitType::f64(res)))
-                    // },
-                    // have the string mirrored/reversed
-                    (StringValue(x), TokenType::Minus) => {
-                        let rev = x.chars().rev().collect::<String>();
-                        Ok(StringValue(rev))
-                    },
-                    (_, TokenType::Minus) => return Err(format!("Minus not implemented for {:?}", val.to_type())),
-                    (any, TokenType::Bang) => Ok(any.is_falsy()),
-                    (_, t_type)=> Err(format!("{} is not a valid unary operator", t_type)),
-                }
+                Self::unary_op(&val, operator.token_type)
+                    .map_err(|msg| format!("{msg} {span}"))
             },
             Self::Logical {
                 left,
                 right,
-                operator
+                operator,
+                span,
             } => {
                 match operator.token_type {
                     TokenType::Or => {
@@ -138,136 +246,304 @@ impl Expr {
                         if lhs_true == True && rhs_true == True { Ok(True) }
                         else { Ok(False) }
                     },
-                    ttype => Err(format!("Invalid token in logical expression: {}", ttype)),
+                    ttype => Err(format!("Invalid token in logical expression: {} {}", ttype, span)),
                 }
             },
             Self::Binary {
                 left,
                 right,
-                operator
+                operator,
+                span,
             } => {
                 let left = left.evaluate(environment.clone())?;
                 let right = right.evaluate(environment.clone())?;
 
-                match (&left, &right, operator.token_type) {
-                    // Standard math calculations /* very basic stuff*/
-                    // bit more complicated now due to the more dynamic types and the derefencing exactly here
-                    (NumberValue(x), NumberValue(y), TokenType::Plus)         => Ok(NumberValue(*x + *y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Minus)        => Ok(NumberValue(*x - *y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Star)         => Ok(NumberValue(*x * *y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Slash)        => Ok(NumberValue(*x / *y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Power)        => Ok(NumberValue((*x).pow(*y))),
-                    (NumberValue(x), NumberValue(y), TokenType::Root)         => Ok(NumberValue((*x).root(*y))),
-                    (NumberValue(x), NumberValue(y), TokenType::Modulo)       => Ok(NumberValue(*x % *y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Greater)      => Ok(LiteralValue::from_bool(x>y)),
-                    (NumberValue(x), NumberValue(y), TokenType::GreaterEqual) => Ok(LiteralValue::from_bool(x>=y)),
-                    (NumberValue(x), NumberValue(y), TokenType::Less)         => Ok(LiteralValue::from_bool(x<y)),
-                    (NumberValue(x), NumberValue(y), TokenType::LessEqual)    => Ok(LiteralValue::from_bool(x<=y)),
-
-                    // (NumberValue(x), NumberValue(y), TokenType::EqualEqual) => Ok(LiteralValue::from_bool(x==y)),
-                    // (NumberValue(x), NumberValue(y), TokenType::BangEqual) => Ok(LiteralValue::from_bool(x!=y)),
-                    // /* starting to get interesting */
-                    (StringValue(s1), StringValue(s2), TokenType::Plus) => Ok(StringValue(format!("{}{}",s1,s2))),
-                    (StringValue(s1), StringValue(s2), TokenType::Minus) => {
-                        let mut result = String::new();
-                        let mut char_freq = std::collections::HashMap::new();
-                        for s2_char in s2.chars() {
-                            let count = char_freq.entry(s2_char).or_insert(0);
-                            *count+=1;
-                        }
-                        for s1_char in s1.chars() {
-                            let count = char_freq.entry(s1_char).or_insert(0);
-                            if *count > 0 {
-                                *count-=1;
-                            } else {
-                                result.push(s1_char);
-                            }
-                        }
-
-                        if result.is_empty() { Ok(Non) }
-                        else { Ok(StringValue(result)) }
-                    },
+                Self::binary_op(&left, &right, operator.token_type)
+                    .map_err(|msg| format!("{msg} {span}"))
+            },
+        }
+    }
+
+    pub fn arguments(arguments: &[Expr], environment: Rc<RefCell<Environment>>) -> Result<Vec<LiteralValue>, String> {
+        let mut arg_vals = vec![];
+        for arg in arguments {
+            let val = arg.evaluate(environment.clone())?;
+            arg_vals.push(val);
+        }
+        Ok(arg_vals)
+    }
 
+    // makes the call, and any tail calls it hands back, in this one loop so they dont pile up on the stack
+    fn call(
+        mut callee: LiteralValue,
+        mut args: Vec<LiteralValue>,
+        environment: Rc<RefCell<Environment>>,
+        mut call_site: Span,
+    ) -> Result<LiteralValue, String> {
+        loop {
+            let (name, arity) = match &callee {
+                Callable { name, arity, .. } => (name.clone(), *arity),
+                Class(class) => (class.name.clone(), class.arity()),
+                other => return Err(format!("{} is not callable. {}", other.to_type(), call_site)),
+            };
+            if args.len() != arity {
+                return Err(format!(
+                    "{} {} expected {} arguments but got {}. {}",
+                    callee.to_type(), name, arity, args.len(), call_site
+                ));
+            }
+
+            call_stack::push(&name, call_site)?;
+            let result = match callee {
+                Class(class) => Self::instantiate(class, &args).map(CallResult::Value),
+                Callable { name: _, arity: _, func, closure } => {
+                    let environment = closure.as_ref().map_or_else(|| environment.clone(), Captured::environment);
+                    func(environment, &args)
+                },
+                _ => unreachable!("checked to be callable above"),
+            };
+            if result.is_err() { call_stack::record_error(); }
+            call_stack::pop();
 
-                    // Combos
-                    // Number and String calculations
-                    (StringValue(s), NumberValue(x), TokenType::Plus) => {Ok(StringValue(format!("{}{}",s,x.to_string())))},
-                    (NumberValue(x), StringValue(s), TokenType::Plus) => {Ok(StringValue(format!("{}{}",x.to_string(),s)))},
-                    
-                    // update thess for float numbers to work
-                    // /* YELL.*/(StringValue(s), NumberValue(x), TokenType::Star) => {
-                    //     let string_res;
-                    //     string_res = s.repeat(*x as usize);
-                    //     Ok(StringValue(string_res))
-                    // },
-                    // /* YELL.*/(NumberValue(x), StringValue(s), TokenType::Star) => {
-                    //     let string_res;
-                    //     string_res = s.repeat(*x as usize);
-                    //     Ok(StringValue(string_res))
-                    // },
-                    
-                    // // num / string, could not work? 
-                    // /* YELL.*/(StringValue(s), NumberValue(x), TokenType::Slash) => {
-                    //     let mut string_res = String::new();
-                    //     // divide the string/string_length by number
-                    //     // loop through the leng
-                    //     let div_string_len = s.len() / *x as usize;
-                    //     if div_string_len == 1 {
-                    //         let first_char = s.chars().nth(0).unwrap().to_string();
-                    //         Ok(StringValue(first_char))
-                    //     }
-                    //     else if div_string_len > 1 {
-                    //         for it in 0..div_string_len {
-                    //             let nth_char = s.chars().nth(it).unwrap();
-                    //             string_res.push(nth_char);
-                    //         }
-                    //         Ok(StringValue(string_res)) 
-                    //     }
-                    //     else { Ok(Non) } 
-                    // },
-
-
-                    (x,y, TokenType::BangEqual) => Ok(LiteralValue::from_bool(x!=y)),
-                    (x,y, TokenType::EqualEqual) => Ok(LiteralValue::from_bool(x==y)),
-                    
-                    (x,y,t_type) => Err(format!(
-                        "{} is not implemented for operands {:?} and {:?}", t_type,x.to_string(),y.to_string()))
+            match result? {
+                CallResult::Value(value) => return Ok(value),
+                CallResult::TailCall { callee: next, args: next_args, call_site: next_site } => {
+                    callee = next;
+                    args = next_args;
+                    call_site = next_site;
+                },
+            }
+        }
+    }
+
+    fn lookup(environment: &Rc<RefCell<Environment>>, name: &str, slot: Option<Slot>) -> Option<LiteralValue> {
+        match slot {
+            Some(slot) => environment.borrow().get_at(slot),
+            None => environment.borrow().get_global(name),
+        }
+    }
+
+    fn assign_variable(environment: &Rc<RefCell<Environment>>, name: &str, slot: Option<Slot>, value: LiteralValue) -> bool {
+        match slot {
+            Some(slot) => environment.borrow_mut().assign_at(slot, value),
+            None => environment.borrow_mut().assign_global(name, value),
+        }
+    }
+
+    // a new instance with its fields set up, then `init` gets called if there is one
+    fn instantiate(class: Rc<RazClass>, args: &Vec<LiteralValue>) -> Result<LiteralValue, String> {
+        let instance = Rc::new(RazInstance::new(class.clone()));
+
+        // superclass fields first so a subclass can override their defaults
+        let mut classes = vec![];
+        let mut current = Some(class.clone());
+        while let Some(c) = current {
+            current = c.superclass.clone();
+            classes.push(c);
+        }
+        for c in classes.iter().rev() {
+            // the same as a method of `c` would see, the resolver gave them the same slots
+            let mut field_env = Environment::new();
+            field_env.enclosing = Some(c.environment.clone());
+            field_env.define("this".into(), Instance(instance.clone()));
+            if let Some(superclass) = &c.superclass {
+                field_env.define("super".into(), Class(superclass.clone()));
+            }
+            let field_env = Rc::new(RefCell::new(field_env));
+
+            for (name, initialiser) in &c.fields {
+                let value = initialiser.evaluate(field_env.clone())?;
+                instance.set(name, value);
+            }
+        }
+
+        match class.find_method("init") {
+            Some((init, owner)) => match bind(init, instance.clone(), &owner) {
+                Callable { func, closure: Some(method_env), .. } => {
+                    func(method_env.environment(), args)?;
+                    Ok(Instance(instance))
+                },
+                _ => unreachable!("methods are always callables"),
+            },
+            None => Ok(Instance(instance)),
+        }
+    }
+
+    fn instance_of(object: &Expr, environment: Rc<RefCell<Environment>>) -> Result<Rc<RazInstance>, String> {
+        match object.evaluate(environment)? {
+            Instance(instance) => Ok(instance),
+            other => Err(format!("Only instances have properties, got {} {}", other.to_type(), object.span())),
+        }
+    }
+
+    fn property(instance: &Rc<RazInstance>, name: &Token) -> Result<LiteralValue, String> {
+        instance.get(&name.lexeme).ok_or_else(|| format!(
+            "{} has no property {:?} {}", instance.class.name, name.lexeme, name.span()
+        ))
+    }
+
+    // the operators themselves, kept apart from `evaluate` so they work on plain values
+    pub fn unary_op(val: &LiteralValue, operator: TokenType) -> Result<LiteralValue, String> {
+        match (val, operator) { // maybe dont give the reference to not derefence so much?
+            // this will be an issue now
+            (NumberValue(x), TokenType::Minus)      => (-(*x)).map(NumberValue),
+            (NumberValue(x), TokenType::Plus)       => Ok(NumberValue(*x)),
+            (NumberValue(x), TokenType::Root)       => (*x).sqrt().map(NumberValue), // square rooting
+            (NumberValue(x), TokenType::Tilde)      => (!(*x)).map(NumberValue),
+            // {
+            //     let res = f64::sqrt(*x);
+            //     Ok(NumberValue(DigitType::f64(res)))
+            // },
+            // have the string mirrored/reversed
+            (StringValue(x), TokenType::Minus) => {
+                let rev = x.chars().rev().collect::<String>();
+                RazString::new(rev).map(StringValue)
+            },
+            (_, TokenType::Minus) => Err(format!("Minus not implemented for {:?}", val.to_type())),
+            (any, TokenType::Bang) => Ok(any.is_falsy()),
+            (_, t_type)=> Err(format!("{} is not a valid unary operator", t_type)),
+        }
+    }
+
+    pub fn binary_op(left: &LiteralValue, right: &LiteralValue, operator: TokenType) -> Result<LiteralValue, String> {
+        match (left, right, operator) {
+            // Standard math calculations /* very basic stuff*/
+            // bit more complicated now due to the more dynamic types and the derefencing exactly here
+            (NumberValue(x), NumberValue(y), TokenType::Plus)         => (*x + *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Minus)        => (*x - *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Star)         => (*x * *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Slash)        => (*x / *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Power)        => (*x).pow(*y).map(NumberValue),
+            // `n /^ x` is the nth root of x
+            (NumberValue(x), NumberValue(y), TokenType::Root)         => (*y).root(*x).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Modulo)       => (*x % *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Ampersand)    => (*x & *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Pipe)         => (*x | *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Xor)          => (*x ^ *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::LessLess)     => (*x << *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::GreaterGreater) => (*x >> *y).map(NumberValue),
+            (NumberValue(x), NumberValue(y), TokenType::Greater)      => Ok(LiteralValue::from_bool(x>y)),
+            (NumberValue(x), NumberValue(y), TokenType::GreaterEqual) => Ok(LiteralValue::from_bool(x>=y)),
+            (NumberValue(x), NumberValue(y), TokenType::Less)         => Ok(LiteralValue::from_bool(x<y)),
+            (NumberValue(x), NumberValue(y), TokenType::LessEqual)    => Ok(LiteralValue::from_bool(x<=y)),
+
+            // (NumberValue(x), NumberValue(y), TokenType::EqualEqual) => Ok(LiteralValue::from_bool(x==y)),
+            // (NumberValue(x), NumberValue(y), TokenType::BangEqual) => Ok(LiteralValue::from_bool(x!=y)),
+            // /* starting to get interesting */
+            (StringValue(s1), StringValue(s2), TokenType::Plus) => {
+                // before making it, `s = s + s` in a loop shouldnt get to allocate the one that doesnt fit
+                memory::fits(s1.len() + s2.len())?;
+                RazString::new(format!("{}{}",s1,s2)).map(StringValue)
+            },
+            (StringValue(s1), StringValue(s2), TokenType::Minus) => {
+                let mut result = String::new();
+                let mut char_freq = std::collections::HashMap::new();
+                for s2_char in s2.chars() {
+                    let count = char_freq.entry(s2_char).or_insert(0);
+                    *count+=1;
+                }
+                for s1_char in s1.chars() {
+                    let count = char_freq.entry(s1_char).or_insert(0);
+                    if *count > 0 {
+                        *count-=1;
+                    } else {
+                        result.push(s1_char);
+                    }
                 }
+
+                if result.is_empty() { Ok(Non) }
+                else { RazString::new(result).map(StringValue) }
             },
-            _ => todo!(),
+
+
+            // Combos
+            // Number and String calculations
+            (StringValue(s), NumberValue(x), TokenType::Plus) => RazString::new(format!("{}{}",s,x)).map(StringValue),
+            (NumberValue(x), StringValue(s), TokenType::Plus) => RazString::new(format!("{}{}",x,s)).map(StringValue),
+            
+            // update thess for float numbers to work
+            // /* YELL.*/(StringValue(s), NumberValue(x), TokenType::Star) => {
+            //     let string_res;
+            //     string_res = s.repeat(*x as usize);
+            //     Ok(StringValue(string_res))
+            // },
+            // /* YELL.*/(NumberValue(x), StringValue(s), TokenType::Star) => {
+            //     let string_res;
+            //     string_res = s.repeat(*x as usize);
+            //     Ok(StringValue(string_res))
+            // },
+            
+            // // num / string, could not work? 
+            // /* YELL.*/(StringValue(s), NumberValue(x), TokenType::Slash) => {
+            //     let mut string_res = String::new();
+            //     // divide the string/string_length by number
+            //     // loop through the leng
+            //     let div_string_len = s.len() / *x as usize;
+            //     if div_string_len == 1 {
+            //         let first_char = s.chars().nth(0).unwrap().to_string();
+            //         Ok(StringValue(first_char))
+            //     }
+            //     else if div_string_len > 1 {
+            //         for it in 0..div_string_len {
+            //             let nth_char = s.chars().nth(it).unwrap();
+            //             string_res.push(nth_char);
+            //         }
+            //         Ok(StringValue(string_res)) 
+            //     }
+            //     else { Ok(Non) } 
+            // },
+
+
+            (x,y, TokenType::BangEqual) => Ok(LiteralValue::from_bool(x!=y)),
+            (x,y, TokenType::EqualEqual) => Ok(LiteralValue::from_bool(x==y)),
+            
+            (x,y,t_type) => Err(format!(
+                "{} is not implemented for operands {:?} and {:?}", t_type,x.to_string(),y.to_string()))
         }
     }
 
     #[allow(dead_code)]
     pub fn to_string(&self) -> String {
         match self {
-            Self::Assignment { name, value } => {
+            Self::Assignment { name, value, slot: _, span: _ } => {
                 format!("({} = {})", name.lexeme, value.to_string())
             }
-            Self::Binary { 
+            Self::Binary {
                 left,
                 right,
                 operator,
+                span: _,
             } => format!(
                 "({} {} {})",
                 left.to_string(),
                 right.to_string(),
                 operator.lexeme,
             ),
-            Self::Call { 
+            Self::Call {
                 callee,
                 paren: _,
-                arguments: _
+                arguments: _,
+                span: _,
             } => format!(
                 "({})",
                 callee.to_string()
             ),
-            Self::Grouping { expression } => format!("({})", (*expression).to_string()), // (*expression).to_string()
-            Self::Literal { value } => format!("{}", value.to_string()),
+            Self::Get { object, name, span: _ } => format!("({}.{})", object.to_string(), name.lexeme),
+            Self::Grouping { expression, span: _ } => format!("({})", (*expression).to_string()), // (*expression).to_string()
+            Self::Interpolation { parts, span: _ } => format!(
+                "(interp {})",
+                parts.iter().map(|part| part.to_string()).collect::<Vec<String>>().join(" ")
+            ),
+            Self::Lambda { params, body: _, span: _ } => format!(
+                "(func ({}))",
+                params.iter().map(|param| param.lexeme.to_string()).collect::<Vec<String>>().join(", ")
+            ),
+            Self::Literal { value, span: _ } => value.to_string(),
             Self::Logical {
                 left,
                 right,
-                operator
+                operator,
+                span: _,
             } => format!(
                 "({} {} {})",
                 left.to_string(),
@@ -277,8 +553,22 @@ impl Expr {
             Self::Unary {
                 val,
                 operator,
+                span: _,
             } => format!("({} {})", operator.lexeme.clone(), (*val).to_string()),
-            Self::Variable { name } => format!("(var {})", name.lexeme),
+            Self::Set { object, name, value, operator, span: _ } => format!(
+                "({}.{} {}= {})",
+                object.to_string(),
+                name.lexeme,
+                operator.as_ref().map_or("", |op| &op.lexeme),
+                value.to_string(),
+            ),
+            Self::Super { keyword: _, method, slot: _, span: _ } => format!("(super.{})", method.lexeme),
+            Self::This { keyword: _, slot: _, span: _ } => "this".to_string(),
+            Self::Variable { name, slot: _, span: _ } => format!("(var {})", name.lexeme),
+            Self::Update { target, operator, prefix, span: _ } => {
+                if *prefix { format!("({}{})", operator.lexeme, target.to_string()) }
+                else { format!("({}{})", target.to_string(), operator.lexeme) }
+            },
         }
     }
 }
@@ -4,7 +4,7 @@ use std::cell::RefCell;
 use std::time::SystemTime;
 
 use crate::digit::*;
-use crate::literals::LiteralValue;
+use crate::literals::{CallResult, LiteralValue};
 use crate::environment::Environment;
 
 // use raz::{
@@ -13,11 +13,11 @@ use crate::environment::Environment;
 //     environment::Environment,
 // };
 
-pub fn clock_impl(_env: Rc<RefCell<Environment>>, _args: &Vec<LiteralValue>) -> LiteralValue {
+pub fn clock_impl(_env: Rc<RefCell<Environment>>, _args: &Vec<LiteralValue>) -> Result<CallResult, String> {
     let now = SystemTime::now()
         .duration_since(std::time::SystemTime::UNIX_EPOCH)
         .expect("Could not get system time.")
         .as_secs_f64();
     // TODO give option to get time in milli, micro and nano
-    LiteralValue::NumberValue(DigitType::f64(now))
+    Ok(CallResult::Value(LiteralValue::NumberValue(DigitType::f64(now))))
 }
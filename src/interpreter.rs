@@ -1,11 +1,16 @@
 
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::HashMap;
 
-use crate::environment::Environment;
-use crate::literals::LiteralValue;
+use crate::budget::{self, Budget};
+use crate::call_stack;
+use crate::memory::{self, Memory};
+use crate::environment::{Captured, Environment};
+use crate::expr::Expr;
+use crate::literals::{CallResult, LiteralValue, RazClass};
 use crate::stmt::Stmt;
-use crate::scanner::Token;
+use crate::scanner::{Span, Token};
 use crate::functions::func::clock_impl;
 
 // use raz::{
@@ -17,145 +22,314 @@ use crate::functions::func::clock_impl;
 // };
 
 pub struct Interpreter { // the environment initialiased is empty
-    // Clean/empty environment
-    specials: Rc<RefCell<Environment>>,
     // Environment with native functions (only `clock` exists)
     environment: Rc<RefCell<Environment>>,
+    // only used by `interpret`, the ones running function calls are inside of it already
+    limits: call_stack::Limits,
+    meter: budget::Meter,
+    // what the values made while it runs are counted against, the ones running function calls dont have their own
+    memory: Option<Rc<Memory>>,
+}
+
+// only the interpreter `new` made owns the globals, the ones running function calls leave them alone.
+// once they're gone whatever was only left over in cycles gets emptied out too
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        match &self.memory {
+            Some(memory) => {
+                self.environment.borrow_mut().clear_globals();
+                memory.collect();
+            },
+            None => Environment::end_scope(&self.environment),
+        }
+    }
+}
+
+// how the statements finished, anything but `Normal` skips the rest of them
+// until it reaches what it's for: the loop with that label (or the innermost one) or the function call
+pub enum ControlFlow {
+    Normal,
+    // a block ended on an expression without `;`, only counts when nothing comes after the block
+    Value(LiteralValue),
+    Return(LiteralValue),
+    // `return f(...)`, the call is left for the caller to make
+    TailCall {
+        callee: LiteralValue,
+        args: Vec<LiteralValue>,
+        call_site: Span,
+    },
+    Break(Option<Rc<str>>),
+    Continue(Option<Rc<str>>),
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        let memory = Memory::new();
+        let outer = memory::switch(Some(memory.clone()));
         let mut natives = Environment::new();
 
         natives.define(
-            "clock".to_string(),
+            "clock".into(),
             LiteralValue::Callable {
-                name: "clock".to_string(),
+                name: "clock".into(),
                 arity: 0,
-                func: Rc::new(clock_impl)
+                func: Rc::new(clock_impl),
+                closure: None,
             });
+        memory::switch(outer);
 
         Self {
-            specials: Rc::new(RefCell::new(Environment::new())),
             environment: Rc::new(RefCell::new(natives)),
+            limits: call_stack::Limits::default(),
+            meter: budget::Meter::default(),
+            memory: Some(memory),
         }
     }
 
+    // how deep raz functions can call into each other before it's a stack overflow error
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.limits.max_depth = depth;
+    }
+
+    // how much of the rust stack raz calls can use before it's a stack overflow error,
+    // it has to be less than the thread running the interpreter has
+    pub fn set_stack_size(&mut self, bytes: usize) {
+        self.limits.stack_size = bytes;
+    }
+
+    // stops the script with a "Budget exceeded" error once it ran out, `take_exceeded` says how far it got.
+    // counting starts now and carries on over every `interpret` after
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.meter = budget::Meter::new(budget);
+    }
+
+    pub fn take_exceeded(&mut self) -> Option<budget::Exceeded> {
+        self.meter.take_exceeded()
+    }
+
+    // how many bytes of values the program can hold on to before it's an "Out of memory" error
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        if let Some(memory) = &self.memory {
+            memory.set_limit(limit);
+        }
+    }
+
+    // how many bytes of values it's holding on to right now
+    pub fn memory_used(&self) -> usize {
+        self.memory.as_ref().map_or(0, |memory| memory.used())
+    }
+
     fn for_closure(parent: Rc<RefCell<Environment>>) -> Self {
         let environment = Rc::new(RefCell::new(Environment::new()));
         environment.borrow_mut().enclosing = Some(parent);
-        Self {
-            specials: Rc::new(RefCell::new(Environment::new())),
-            environment
+        Self { environment, limits: call_stack::Limits::default(), meter: budget::Meter::default(), memory: None }
+    }
+
+    // the callable for a function or method declaration, it runs inside of `closure`, the environment
+    // it was declared in. methods dont have one, `bind` gives them the environment `this` is defined in
+    pub fn function(name: Rc<str>, params: &Rc<[Token]>, body: &Rc<[Stmt]>, closure: Option<Rc<RefCell<Environment>>>) -> LiteralValue {
+        let arity = params.len();
+
+        // every callable of the same declaration points at the same parsed body
+        let params = params.clone();
+        let body = body.clone();
+
+        let func_impl =
+            move |parent_env, args: &Vec<LiteralValue>|
+        {
+            let mut clos_int = Interpreter::for_closure(parent_env);
+
+            for (i, arg) in args.iter().enumerate() {
+                clos_int
+                    .environment
+                    .borrow_mut()
+                    .define(params[i].lexeme.clone(), (*arg).clone());
+            }
+
+            let flow = clos_int.execute(body.iter().collect())?;
+            match flow {
+                ControlFlow::Return(value) | ControlFlow::Value(value) => Ok(CallResult::Value(value)),
+                ControlFlow::TailCall { callee, args, call_site } => Ok(CallResult::TailCall { callee, args, call_site }),
+                _ => Ok(CallResult::Value(LiteralValue::Non)),
+            }
+        };
+        LiteralValue::Callable {
+            name,
+            arity,
+            func: Rc::new(func_impl),
+            closure: closure.map(Captured::Strong),
         }
     }
 
-    pub fn interpret(&mut self, stmts: Vec<&Stmt>) -> Result<(), String> {
-        for stmt in stmts {
+    // runs a program, or a line of the prompt. a runtime error that came out of a raz call
+    // gets the calls it happened in added under it
+    pub fn interpret(&mut self, stmts: Vec<&Stmt>) -> Result<ControlFlow, String> {
+        let entered = call_stack::enter(self.limits);
+        let outer = budget::switch(self.meter);
+        let outer_memory = memory::switch(self.memory.clone());
+        let result = self.execute(stmts);
+        memory::switch(outer_memory);
+        self.meter = budget::switch(outer);
+        call_stack::leave(entered);
+        result.map_err(|err| match call_stack::take_traceback() {
+            Some(traceback) if !traceback.is_empty() => format!("{err}\n{traceback}"),
+            _ => err,
+        })
+    }
+
+    // the statements of blocks and function bodies, they leave the traceback for `interpret`
+    fn execute(&mut self, stmts: Vec<&Stmt>) -> Result<ControlFlow, String> {
+        let count = stmts.len();
+        for (i, stmt) in stmts.into_iter().enumerate() {
+            let is_last = i + 1 == count;
+            budget::step(|| stmt.span())?;
+            memory::check().map_err(|msg| format!("{msg} {}", stmt.span()))?;
             let env = self.environment.clone();
             match stmt {
-                Stmt::Expression { expression } => {
+                Stmt::Expression { expression, span: _ } => {
                     expression.evaluate(env)?;
                 },
-                Stmt::Print { expression } => {
+                Stmt::Tail { expression, span: _ } => {
+                    let value = expression.evaluate(env)?;
+                    return Ok(ControlFlow::Value(value));
+                },
+                Stmt::Print { expression, span: _ } => {
                     let value = expression.evaluate(env)?;
                     println!("\"{}\"", value.to_string());
                 },
-                Stmt::Var { name, initialiser } => {
+                Stmt::Var { name, initialiser, span: _ } => {
                     let value = initialiser.evaluate(self.environment.clone())?;
 
                     self.environment.borrow_mut().define(name.lexeme.clone(), value);
                 },
-                Stmt::Block { statements } => {
+                Stmt::Block { statements, span: _ } => {
                     let mut new_environment = Environment::new();
                     new_environment.enclosing = Some(self.environment.clone());
                     let old_environment = self.environment.clone();
                     self.environment = Rc::new(RefCell::new(new_environment));
-                    let block_result = self.interpret(
+                    let block_result = self.execute(
                         (*statements).iter().map(|b| b.as_ref()).collect()
                     );
+                    Environment::end_scope(&self.environment);
                     self.environment = old_environment;
 
-                    block_result?;
+                    match block_result? {
+                        ControlFlow::Normal => (),
+                        ControlFlow::Value(_) if !is_last => (),
+                        flow => return Ok(flow),
+                    }
                 },
                 Stmt::IfStmt {
                     condition,
                     then_branch,
-                    else_branch
+                    else_branch,
+                    span: _,
                 } => {
                     let truth_value = condition.evaluate(self.environment.clone())?;
-                    if truth_value.is_truthy() == LiteralValue::True {
-                        self.interpret(vec![then_branch])?;
+                    let flow = if truth_value.is_truthy() == LiteralValue::True {
+                        self.execute(vec![then_branch])?
                     } else if let Some(else_stmt) = else_branch {
-                        self.interpret(vec![else_stmt])?;
+                        self.execute(vec![else_stmt])?
+                    } else { ControlFlow::Normal };
+                    match flow {
+                        ControlFlow::Normal => (),
+                        ControlFlow::Value(_) if !is_last => (),
+                        flow => return Ok(flow),
                     }
                 },
-                Stmt::WhileStmt { condition, body } => {
+                Stmt::WhileStmt { condition, body, increment, label, span: _ } => {
+                    let label = label.as_ref().map(|l| &l.lexeme);
                     let mut flag = condition.evaluate(self.environment.clone())?;
                     while flag.is_truthy() == LiteralValue::True { // heh
-                        self.interpret(vec![body])?;
+                        // a jump without a label is for the innermost loop
+                        match self.execute(vec![body])? {
+                            ControlFlow::Break(target) if target.is_none() || target.as_ref() == label => break,
+                            ControlFlow::Continue(target) if target.is_none() || target.as_ref() == label => (),
+                            ControlFlow::Normal | ControlFlow::Value(_) => (),
+                            // a return, or a jump for a loop further out
+                            flow => return Ok(flow),
+                        }
+
+                        if let Some(increment) = increment {
+                            increment.evaluate(self.environment.clone())?;
+                        }
                         flag = condition.evaluate(self.environment.clone())?;
                     }
                 },
+                Stmt::Break { keyword: _, label, span: _ } => {
+                    return Ok(ControlFlow::Break(label.as_ref().map(|l| l.lexeme.clone())));
+                },
+                Stmt::Continue { keyword: _, label, span: _ } => {
+                    return Ok(ControlFlow::Continue(label.as_ref().map(|l| l.lexeme.clone())));
+                },
                 Stmt::Function {
                     name,
                     params,
-                    body
+                    body,
+                    span: _,
                 } => {
-                    let arity = params.len();
-
-                    let params: Vec<Token> = params.iter().map(|t| (*t).clone()).collect();
-                    let body: Vec<Box<Stmt>> = body.iter().map(|b| (*b).clone()).collect();
-                    let name_clone = name.lexeme.clone();
-
-                    let func_impl =
-                        move |parent_env, args: &Vec<LiteralValue>|
-                    {
-                        let mut clos_int = Interpreter::for_closure(parent_env);
-
-                        for (i, arg) in args.iter().enumerate() {
-                            clos_int
-                                .environment
-                                .borrow_mut()
-                                .define(params[i].lexeme.clone(), (*arg).clone());
-                        }
+                    let callable = Self::function(name.lexeme.clone(), params, body, Some(self.environment.clone()));
+                    self.environment.borrow_mut().define(name.lexeme.clone(), callable);
+                },
+                Stmt::Class {
+                    name,
+                    superclass,
+                    fields,
+                    methods,
+                    span: _,
+                } => {
+                    let superclass = match superclass {
+                        Some(expr) => match expr.evaluate(self.environment.clone())? {
+                            LiteralValue::Class(class) => Some(class),
+                            other => return Err(format!(
+                                "Superclass of {} has to be a class, got {} {}",
+                                name.lexeme, other.to_type(), expr.span()
+                            )),
+                        },
+                        None => None,
+                    };
 
-                        for i in 0..(body.len()) {
-                            clos_int.interpret(vec![body[i].as_ref()]).expect(&format!(
-                                "Evaluating failed inside {}",
-                                name_clone
-                            ));
-                            if let Some(value) = clos_int.specials.borrow().get("return") {
-                                return value;
-                            }
+                    let mut class_methods = HashMap::new();
+                    for method in methods {
+                        if let Stmt::Function { name, params, body, span: _ } = method {
+                            class_methods.insert(name.lexeme.clone(), Self::function(name.lexeme.clone(), params, body, None));
                         }
-                        LiteralValue::Non
-                    };
-                    let callable = LiteralValue::Callable {
+                    }
+                    let fields = fields.iter().filter_map(|field| match field {
+                        Stmt::Var { name, initialiser, span: _ } => Some((name.lexeme.clone(), initialiser.clone())),
+                        _ => None,
+                    }).collect();
+
+                    let class = RazClass {
                         name: name.lexeme.clone(),
-                        arity,
-                        func: Rc::new(func_impl),
+                        superclass,
+                        methods: class_methods,
+                        fields,
+                        environment: self.environment.clone(),
                     };
-
-                    self.environment.borrow_mut().define(name.lexeme.clone(), callable);
+                    self.environment.borrow_mut().define(name.lexeme.clone(), LiteralValue::Class(Rc::new(class)));
                 },
                 Stmt::ReturnStmt {
                     keyword: _,
-                    value
+                    value,
+                    span: _,
                 } => {
+                    if let Some(Expr::Call { callee: callee_expr, paren: _, arguments, span: _ }) = value {
+                        let callee = callee_expr.evaluate(self.environment.clone())?;
+                        let args = Expr::arguments(arguments, self.environment.clone())?;
+                        return Ok(ControlFlow::TailCall { callee, args, call_site: callee_expr.span() });
+                    }
+
                     let eval_val;
                     if let Some(value) = value {
                         eval_val = value.evaluate(self.environment.clone())?;
                     } else {
                         eval_val = LiteralValue::Non;
                     }
-                    self.specials
-                        .borrow_mut()
-                        .define_top_level("return".to_string(), eval_val);
-
+                    return Ok(ControlFlow::Return(eval_val));
                 },
             };
         }
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 }
@@ -3,17 +3,24 @@
 
 pub mod runner;
 
+pub mod budget;
+pub mod call_stack;
 pub mod environment;
 pub mod expr;
 pub mod literals;
+pub mod memory;
+pub mod optimiser;
 pub mod digit;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
 pub mod stmt;
 pub mod functions;
 
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
 pub mod interpreter;
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+pub mod vm;
 
 #[cfg(all(feature = "compiler", not(feature = "interpreter")))]
 pub mod compiler;
@@ -1,8 +1,11 @@
 
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 
-use crate::environment::Environment;
+use crate::environment::{Captured, Environment, Slot};
+use crate::expr::Expr;
+use crate::memory::{self, Charge, Collect, Memory};
 use crate::scanner::{self, Token, TokenType};
 use crate::digit::*;
 
@@ -12,25 +15,242 @@ use crate::digit::*;
 //     scanner::{self, Token, TokenType}
 // };
 
+// errors from inside of the call come back the same as any other runtime error
+pub type NativeFn = dyn Fn(Rc<RefCell<Environment>>, &Vec<LiteralValue>) -> Result<CallResult, String>;
+
+// a function ending in `return f(...)` hands that call back to whoever called it instead of making it
+// itself, so recursing like that doesnt keep growing the stack
+pub enum CallResult {
+    Value(LiteralValue),
+    TailCall {
+        callee: LiteralValue,
+        args: Vec<LiteralValue>,
+        call_site: scanner::Span,
+    },
+}
+
 #[derive(Clone)]
 pub enum LiteralValue {
     NumberValue(DigitType),
-    StringValue(String),
+    StringValue(RazString),
     True,
     False,
     Non,
     Callable {
-        name: String,
+        name: Rc<str>,
         arity: usize,
-        func: Rc<dyn Fn(
-            Rc<RefCell<Environment>>,
-            &Vec<LiteralValue>
-        ) -> LiteralValue>,
+        func: Rc<NativeFn>,
+        // the environment a raz function was declared in, it gets called with that one instead of the caller's.
+        // natives dont need one and methods get theirs from `bind`
+        closure: Option<Captured>,
     },
+    Class(Rc<RazClass>),
+    Instance(Rc<RazInstance>),
 }
 
 use LiteralValue::*;
 
+// the text of a string value, shared so copying the value around doesnt copy the text.
+// it counts towards the memory limit until the last copy of it is gone
+#[derive(Clone)]
+pub struct RazString {
+    text: Rc<str>,
+    // the memory the text is counted against, every copy points at it so whichever is last can give it back
+    memory: Option<Rc<Memory>>,
+}
+
+impl RazString {
+    // an error instead when the text doesnt fit in the memory limit
+    pub fn new(text: String) -> Result<Self, String> {
+        memory::fits(text.len())?;
+        Ok(Self::counted(text.into()))
+    }
+
+    // string literals of the program, those are there either way
+    pub fn literal(text: &str) -> Self {
+        Self::counted(text.into())
+    }
+
+    fn counted(text: Rc<str>) -> Self {
+        let memory = memory::charge(text.len());
+        Self { text, memory }
+    }
+}
+
+impl PartialEq for RazString {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl std::ops::Deref for RazString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Display for RazString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Drop for RazString {
+    fn drop(&mut self) {
+        if let (1, Some(memory)) = (Rc::strong_count(&self.text), &self.memory) {
+            memory.release(self.text.len());
+        }
+    }
+}
+
+pub struct RazClass {
+    pub name: Rc<str>,
+    pub superclass: Option<Rc<RazClass>>,
+    // unbound, `this` only gets defined once they're taken from an instance
+    pub methods: HashMap<Rc<str>, LiteralValue>,
+    // `var x = 0;` in the class body, evaluated again for every new instance
+    pub fields: Vec<(Rc<str>, Expr)>,
+    // where the class was declared, the field initialisers run in there
+    pub environment: Rc<RefCell<Environment>>,
+}
+
+impl RazClass {
+    // looks through the superclasses too, gives back the class the method was found in
+    pub fn find_method(self: &Rc<Self>, name: &str) -> Option<(LiteralValue, Rc<RazClass>)> {
+        match (self.methods.get(name), &self.superclass) {
+            (Some(method), _) => Some((method.clone(), self.clone())),
+            (None, Some(superclass)) => superclass.find_method(name),
+            (None, None) => None,
+        }
+    }
+
+    // `init` decides how many arguments calling the class takes
+    pub fn arity(self: &Rc<Self>) -> usize {
+        match self.find_method("init") {
+            Some((Callable { arity, .. }, _)) => arity,
+            _ => 0,
+        }
+    }
+}
+
+pub struct RazInstance {
+    pub class: Rc<RazClass>,
+    pub fields: RefCell<HashMap<Rc<str>, LiteralValue>>,
+    // counted against the memory limit with a value's worth for every field, like an environment
+    charge: RefCell<Charge>,
+    // it's been handed to `memory::suspect` already
+    suspected: Cell<bool>,
+}
+
+impl RazInstance {
+    pub fn new(class: Rc<RazClass>) -> Self {
+        let charge = RefCell::new(Charge::new(std::mem::size_of::<Self>()));
+        Self { class, fields: RefCell::new(HashMap::new()), charge, suspected: Cell::new(false) }
+    }
+
+    // fields first so they can shadow methods
+    pub fn get(self: &Rc<Self>, name: &str) -> Option<LiteralValue> {
+        if let Some(value) = self.fields.borrow().get(name) {
+            return Some(value.clone());
+        }
+        self.class.find_method(name)
+            .map(|(method, owner)| bind(method, self.clone(), &owner))
+    }
+
+    // a field pointing at something that can point back might make a cycle
+    pub fn set(self: &Rc<Self>, name: &Rc<str>, value: LiteralValue) {
+        if !self.suspected.get() && value.can_point_back() {
+            self.suspected.set(true);
+            let instance: Weak<RazInstance> = Rc::downgrade(self);
+            memory::suspect(instance);
+        }
+        if self.fields.borrow_mut().insert(name.clone(), value).is_none() {
+            self.charge.borrow_mut().grow(std::mem::size_of::<LiteralValue>());
+        }
+    }
+}
+
+impl Collect for RazClass {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        if let Some(superclass) = &self.superclass {
+            found.push(superclass.clone());
+        }
+        found.push(self.environment.clone());
+        for method in self.methods.values() {
+            method.edges(found);
+        }
+        true
+    }
+
+    // a class cant change, the environment it's in gets emptied out instead
+    fn clear(self: &Self) {}
+}
+
+impl Collect for RazInstance {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        let Ok(fields) = self.fields.try_borrow() else { return false };
+        found.push(self.class.clone());
+        for value in fields.values() {
+            value.edges(found);
+        }
+        true
+    }
+
+    fn clear(self: &Self) {
+        let Ok(mut fields) = self.fields.try_borrow_mut() else { return };
+        let values = std::mem::take(&mut *fields);
+        drop(fields);
+        drop(values);
+    }
+}
+
+// a method taken from an instance, runs with `this` (and `super` of the class it's declared in) defined
+// in between it and the environment its class was declared in
+pub fn bind(method: LiteralValue, instance: Rc<RazInstance>, owner: &RazClass) -> LiteralValue {
+    match method {
+        Callable { name, arity, func, closure: _ } => {
+            let mut method_env = Environment::new();
+            method_env.enclosing = Some(owner.environment.clone());
+            method_env.define("this".into(), Instance(instance));
+            if let Some(superclass) = &owner.superclass {
+                method_env.define("super".into(), Class(superclass.clone()));
+            }
+            let is_init = &*name == "init";
+            // it gets called with `method_env`, the instance is only kept in there so `memory::collect` can see it
+            let bound = move |method_env: Rc<RefCell<Environment>>, args: &Vec<LiteralValue>| {
+                let this = method_env.borrow().get_at(Slot { depth: 0, index: 0 });
+                let result = func(method_env, args)?;
+                // `init` always gives back the instance
+                match (is_init, this) {
+                    (true, Some(this)) => Ok(CallResult::Value(this)),
+                    _ => Ok(result),
+                }
+            };
+            let closure = Some(Captured::Strong(Rc::new(RefCell::new(method_env))));
+            Callable { name, arity, func: Rc::new(bound), closure }
+        },
+        other => other,
+    }
+}
+
+impl LiteralValue {
+    // the pointers `memory::Collect` follows, weak closures dont keep anything alive
+    pub fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) {
+        match self {
+            Callable { closure: Some(Captured::Strong(env)), .. } => found.push(env.clone()),
+            Class(class) => found.push(class.clone()),
+            Instance(instance) => found.push(instance.clone()),
+            _ => (),
+        }
+    }
+
+    fn can_point_back(self: &Self) -> bool {
+        matches!(self, Callable { closure: Some(Captured::Strong(_)), .. } | Class(_) | Instance(_))
+    }
+}
+
 impl std::fmt::Debug for LiteralValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.to_string())
@@ -41,10 +261,12 @@ impl PartialEq for LiteralValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (NumberValue(x), NumberValue(y)) => x == y,
-            (Callable { name, arity, func: _ },
-            Callable { name: name2, arity: arity2, func: _}
-            ) => name == name2 && arity == arity2,
+            // the same function, every copy of a callable shares its `func`. a method gets a new one each time
+            // it's taken from an instance, same as the vm's bound methods
+            (Callable { func, .. }, Callable { func: func2, .. }) => Rc::ptr_eq(func, func2),
             (StringValue(s1), StringValue(s2)) => s1 == s2,
+            (Class(c1), Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Instance(i1), Instance(i2)) => Rc::ptr_eq(i1, i2),
             (True, True) => true,
             (False, False) => true,
             (Non, Non) => true,
@@ -53,9 +275,16 @@ impl PartialEq for LiteralValue {
     }
 }
 
-fn unwrap_as_string(literal: Option<scanner::LiteralValue>) -> String {
+fn unwrap_as_number(literal: &Option<scanner::LiteralValue>) -> DigitType {
+    match literal {
+        Some(scanner::LiteralValue::NumberValue(x)) => *x,
+        _ => panic!("Could not unwrap as number")
+    }
+}
+
+fn unwrap_as_string(literal: &Option<scanner::LiteralValue>) -> RazString {
     match literal {
-        Some(scanner::LiteralValue::StringValue(s)) => s.clone(),
+        Some(scanner::LiteralValue::StringValue(s)) => RazString::literal(s),
         _ => panic!("Could not unwrap as string")
     }
 }
@@ -68,10 +297,11 @@ impl LiteralValue {
             Self::True => "true".to_string(),
             Self::False => "false".to_string(),
             Self::Non => "Non".to_string(),
-            Self::Callable { name, arity, func: _ } => {
+            Self::Callable { name, arity, func: _, closure: _ } => {
                 format!("{name}_{arity}")
             },
-            _ => panic!("Cannot convert LiteralValue into string"),
+            Self::Class(class) => class.name.to_string(),
+            Self::Instance(instance) => format!("{} instance", instance.class.name),
         }
     }
 
@@ -85,16 +315,18 @@ impl LiteralValue {
             Self::Callable { 
                 name: _,
                 arity: _,
-                func: _
+                func: _,
+                closure: _,
             } => "Callable",
-            _ => panic!("Cannot check unknown LiteralValue"),
+            Self::Class(_) => "Class",
+            Self::Instance(_) => "Instance",
         }
     }
 
-    pub fn from_token(token: Token) -> Self {
+    pub fn from_token(token: &Token) -> Self {
         match token.token_type {
-            TokenType::Number => Self::NumberValue(DigitType::from_string(token.lexeme)),
-            TokenType::StringLit => Self::StringValue(unwrap_as_string(token.literal)),
+            TokenType::Number => Self::NumberValue(unwrap_as_number(&token.literal)),
+            TokenType::StringLit | TokenType::StringPart => Self::StringValue(unwrap_as_string(&token.literal)),
             TokenType::False => Self::False,
             TokenType::True => Self::True,
             TokenType::Non => Self::Non,
@@ -114,13 +346,14 @@ impl LiteralValue {
                 else { False }
             },
             StringValue(s) => {
-                if s.len() == 0 { True }
+                if s.is_empty() { True }
                 else { False }
             },
             True => False,
             False => True,
             Non => True,
-            unkn => panic!("Cannot use {} as a falsy value.", unkn.to_string()),
+            // functions, classes and instances are always there
+            Callable { .. } | Class(_) | Instance(_) => False,
         }
     }
 
@@ -131,13 +364,13 @@ impl LiteralValue {
                 else { True }
             },
             StringValue(s) => {
-                if s.len() == 0 { False }
+                if s.is_empty() { False }
                 else { True }
             },
             True => True,
             False => False,
             Non => False,
-            unkn => panic!("Cannot use {} as a truthy value.", unkn.to_string()),
+            Callable { .. } | Class(_) | Instance(_) => True,
         }
     }
 }
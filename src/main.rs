@@ -6,17 +6,34 @@ use raz::runner::*;
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
 fn main() {
     // might todo later the args here kinda got a nicer method of doing those
-    let args: Vec<String> = args().collect();
+    let mut args: Vec<String> = args().collect();
+    // `--vm` anywhere runs it on the bytecode vm instead of the interpreter
+    let backend = match args.iter().position(|arg| arg == "--vm") {
+        Some(index) => {
+            args.remove(index);
+            Backend::Vm
+        },
+        None => Backend::Interpreter,
+    };
+    // `--optimise` folds the constant parts of the code before it runs
+    let optimise = match args.iter().position(|arg| arg == "--optimise") {
+        Some(index) => {
+            args.remove(index);
+            true
+        },
+        None => false,
+    };
+    let options = Options { backend, optimise };
     // if given more arguments than 2: `raz file1.raz file2.raz`
     // exit out // why thats kinda stupid
     if args.len() > 2 {
-        println!("Usage:\n\traz [file]"); // amazing help
+        println!("Usage:\n\traz [--vm] [--optimise] [file]"); // amazing help
     }
     // other wise if its just 2: `raz file.rz` execute mention file
     else if args.len() == 2 {
         // make sure it's a .raz file
         if args[1].ends_with(".rz") || args[1].ends_with(".raz")  { // atm the file extension has no difference
-            match run_file(&args[1]) {
+            match run_file(&args[1], options) {
                 Ok(_) => exit(0),
                 Err(msg) => eprintln!("ERROR:\n\t{msg}")
             }
@@ -24,7 +41,7 @@ fn main() {
     }
     // use the interactive mode, similar to one as python
     else {
-        match run_prompt() {
+        match run_prompt(options) {
             Ok(_) => exit(0),
             Err(msg) => {
                 eprintln!("ERROR:\n\t{msg}");
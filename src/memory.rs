@@ -0,0 +1,262 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+// roughly how many bytes the values of one interpreter or vm are holding on to, so a program that keeps
+// growing gets a raz error instead of the host running out of memory. strings count their text, environments
+// and instances a value's worth per variable or field. each interpreter and vm has its own, what gets made
+// while one of them runs is counted against it and given back to that same one when it's dropped
+pub struct Memory {
+    limit: Cell<Option<usize>>,
+    used: Cell<usize>,
+    // what might be holding on to itself through a cycle, see `Collect`
+    suspects: RefCell<Vec<Weak<dyn Collect>>>,
+    // `suspects` forgets the ones that are gone already once it gets this long
+    prune_at: Cell<usize>,
+    // cycles get looked for once this much is in use, and again each time it doubles
+    collect_at: Cell<usize>,
+}
+
+// what values can point at each other through: environments, classes and instances for the interpreter, closures,
+// upvalues, classes, instances and bound methods for the vm. a function stored in the scope it closes over or an
+// instance holding itself keeps its `Rc`s above zero forever, so every so often the ones that might be are looked
+// through. anything with more pointers to it than the others found account for is still in use, and so is all
+// it points at. what's left is only holding on to itself and gets emptied out, which lets it drop
+pub trait Collect {
+    // the pointers it holds to other things that can be collected, one for every `Rc` it keeps.
+    // `false` when it's borrowed right now and cant be looked at, it's in use anyway then
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool;
+
+    // drops what it holds on to, only ever called when nothing else can reach it
+    fn clear(self: &Self);
+}
+
+// the first look for cycles, and the fewest suspects kept before forgetting the dropped ones
+const FIRST_COLLECTION: usize = 64 * 1024;
+const FIRST_PRUNE: usize = 256;
+
+thread_local! {
+    // the memory of whatever is running right now, nothing made while nothing runs gets counted
+    static CURRENT: RefCell<Option<Rc<Memory>>> = const { RefCell::new(None) };
+}
+
+impl Memory {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self {
+            limit: Cell::new(None),
+            used: Cell::new(0),
+            suspects: RefCell::new(vec![]),
+            prune_at: Cell::new(FIRST_PRUNE),
+            collect_at: Cell::new(FIRST_COLLECTION),
+        })
+    }
+
+    // `None` for no limit, which is the default
+    pub fn set_limit(self: &Self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    pub fn used(self: &Self) -> usize {
+        self.used.get()
+    }
+
+    fn fits(self: &Self, bytes: usize) -> Result<(), String> {
+        let used = self.used.get();
+        match self.limit.get() {
+            Some(limit) if used.saturating_add(bytes) > limit => Err(format!(
+                "Out of memory, {bytes} more bytes go over the limit of {limit} with {used} already in use"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn charge(self: &Self, bytes: usize) {
+        self.used.set(self.used.get().saturating_add(bytes));
+    }
+
+    pub fn release(self: &Self, bytes: usize) {
+        self.used.set(self.used.get().saturating_sub(bytes));
+    }
+
+    fn check(self: &Self) -> Result<(), String> {
+        let used = self.used.get();
+        match self.limit.get() {
+            Some(limit) if used > limit => Err(format!("Out of memory, using {used} bytes of the {limit} allowed")),
+            _ => Ok(()),
+        }
+    }
+
+    fn suspect(self: &Self, node: Weak<dyn Collect>) {
+        let mut suspects = self.suspects.borrow_mut();
+        suspects.push(node);
+        if suspects.len() >= self.prune_at.get() {
+            suspects.retain(|suspect| suspect.strong_count() > 0);
+            self.prune_at.set((suspects.len() * 2).max(FIRST_PRUNE));
+        }
+    }
+
+    // empties out every cycle among the suspects that nothing else points at
+    pub fn collect(self: &Self) {
+        let suspects = self.suspects.take();
+        let mut graph = Graph::default();
+        for suspect in &suspects {
+            if let Some(node) = suspect.upgrade() {
+                graph.add(node);
+            }
+        }
+        graph.explore();
+        let kept = graph.kept();
+        for (node, kept) in graph.nodes.iter().zip(&kept) {
+            if !kept { node.clear(); }
+        }
+
+        // the ones still in use might still end up in a cycle later
+        let mut still = suspects.into_iter()
+            .filter(|suspect| suspect.upgrade().is_some_and(|node| kept[graph.index[&key(&node)]]))
+            .collect::<Vec<_>>();
+        drop(graph);
+        let mut suspects = self.suspects.borrow_mut();
+        still.append(&mut suspects);
+        *suspects = still;
+        self.prune_at.set((suspects.len() * 2).max(FIRST_PRUNE));
+        self.collect_at.set((self.used.get() * 2).max(FIRST_COLLECTION));
+    }
+}
+
+fn key(node: &Rc<dyn Collect>) -> *const () {
+    Rc::as_ptr(node) as *const ()
+}
+
+// everything reachable from the suspects, with how many pointers to each come from inside of it
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Rc<dyn Collect>>,
+    index: HashMap<*const (), usize>,
+    edges: Vec<Vec<usize>>,
+    pointed_at: Vec<usize>,
+    // it was borrowed so its edges arent known
+    busy: Vec<bool>,
+}
+
+impl Graph {
+    fn add(self: &mut Self, node: Rc<dyn Collect>) -> usize {
+        if let Some(&index) = self.index.get(&key(&node)) {
+            return index;
+        }
+        let index = self.nodes.len();
+        self.index.insert(key(&node), index);
+        self.nodes.push(node);
+        self.edges.push(vec![]);
+        self.pointed_at.push(0);
+        self.busy.push(false);
+        index
+    }
+
+    // follows the edges of everything added, and of everything those lead to
+    fn explore(self: &mut Self) {
+        let mut next = 0;
+        while next < self.nodes.len() {
+            let mut found = vec![];
+            self.busy[next] = !self.nodes[next].edges(&mut found);
+            for node in found {
+                let target = self.add(node);
+                self.edges[next].push(target);
+                self.pointed_at[target] += 1;
+            }
+            next += 1;
+        }
+    }
+
+    // what something outside of the graph points at is in use, the graph holds one pointer to each itself
+    fn kept(self: &Self) -> Vec<bool> {
+        let mut kept = vec![false; self.nodes.len()];
+        let mut reached = (0..self.nodes.len())
+            .filter(|&i| self.busy[i] || Rc::strong_count(&self.nodes[i]) - 1 > self.pointed_at[i])
+            .collect::<Vec<_>>();
+        while let Some(i) = reached.pop() {
+            if kept[i] { continue; }
+            kept[i] = true;
+            reached.extend(&self.edges[i]);
+        }
+        kept
+    }
+}
+
+// new values get counted against `memory` from now on, what they were counted against gets handed back
+pub fn switch(memory: Option<Rc<Memory>>) -> Option<Rc<Memory>> {
+    CURRENT.replace(memory)
+}
+
+fn current() -> Option<Rc<Memory>> {
+    CURRENT.with_borrow(|memory| memory.clone())
+}
+
+// whether `bytes` more would still fit, checked before something big gets built
+pub fn fits(bytes: usize) -> Result<(), String> {
+    let Some(memory) = current() else { return Ok(()) };
+    if memory.fits(bytes).is_err() {
+        memory.collect();
+    }
+    memory.fits(bytes)
+}
+
+// the interpreter looks at this before every statement, it's also when cycles get looked for
+pub fn check() -> Result<(), String> {
+    let Some(memory) = current() else { return Ok(()) };
+    if memory.used() > memory.collect_at.get() || memory.check().is_err() {
+        memory.collect();
+    }
+    memory.check()
+}
+
+// something that might be part of a cycle, the next `collect` looks at it
+pub fn suspect(node: Weak<dyn Collect>) {
+    if let Some(memory) = current() {
+        memory.suspect(node);
+    }
+}
+
+// counts `bytes` against what's running and hands back what they were counted against, for values
+// that give them back themselves instead of holding a `Charge`
+pub fn charge(bytes: usize) -> Option<Rc<Memory>> {
+    let memory = current();
+    if let Some(memory) = &memory {
+        memory.charge(bytes);
+    }
+    memory
+}
+
+// bytes counted against the memory that was running when it was made. dropping it gives
+// them back to that one, whichever is running by then
+pub struct Charge {
+    memory: Option<Rc<Memory>>,
+    bytes: usize,
+}
+
+impl Charge {
+    // counted even when it goes over, for what cant fail where it's made. `check` catches it after
+    pub fn new(bytes: usize) -> Self {
+        Self { memory: charge(bytes), bytes }
+    }
+
+    pub fn grow(self: &mut Self, bytes: usize) {
+        if let Some(memory) = &self.memory {
+            memory.charge(bytes);
+        }
+        self.bytes += bytes;
+    }
+
+    pub fn shrink(self: &mut Self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        if let Some(memory) = &self.memory {
+            memory.release(bytes);
+        }
+        self.bytes -= bytes;
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.shrink(self.bytes);
+    }
+}
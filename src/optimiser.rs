@@ -0,0 +1,215 @@
+use std::rc::Rc;
+
+use crate::expr::Expr;
+use crate::literals::LiteralValue;
+use crate::scanner::TokenType;
+use crate::stmt::Stmt;
+
+// optional pass after resolving (`raz --optimise`), works out the expressions that only use literals
+// once instead of every time they run and drops the `if` branches that can never run.
+// arithmetic that fails on literals (overflow, division by zero...) is left as it is, it only
+// is an error if it runs. it gets a warning before anything runs though
+pub struct Optimiser {
+    warnings: Vec<String>,
+}
+
+impl Optimiser {
+    pub fn new() -> Self {
+        Self { warnings: vec![] }
+    }
+
+    // gives back the warnings
+    pub fn optimise(self: &mut Self, stmts: &mut [Stmt]) -> Vec<String> {
+        for stmt in stmts.iter_mut() {
+            self.statement(stmt);
+        }
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn statement(self: &mut Self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Block { statements, span: _ } => {
+                for stmt in statements.iter_mut() {
+                    self.statement(stmt);
+                }
+            },
+            Stmt::Break { .. } | Stmt::Continue { .. } => (),
+            Stmt::Class { name: _, superclass: _, fields, methods, span: _ } => {
+                for stmt in fields.iter_mut().chain(methods.iter_mut()) {
+                    self.statement(stmt);
+                }
+            },
+            Stmt::Expression { expression, span: _ }
+            | Stmt::Print { expression, span: _ }
+            | Stmt::Tail { expression, span: _ } => self.expression(expression),
+            Stmt::Function { name: _, params: _, body, span: _ } => self.function(body),
+            Stmt::IfStmt { condition, then_branch, else_branch, span } => {
+                self.expression(condition);
+                let Expr::Literal { value, span: _ } = condition else {
+                    self.statement(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.statement(else_branch);
+                    }
+                    return;
+                };
+
+                // only the branch that runs is left, in place of the whole `if`.
+                // with nothing to run it's an empty block so a block ending in it still ends the same
+                let empty = Stmt::Block { statements: vec![], span: *span };
+                let branch = if value.is_truthy() == LiteralValue::True {
+                    std::mem::replace(then_branch.as_mut(), empty)
+                } else {
+                    else_branch.take().map_or(empty, |branch| *branch)
+                };
+                *stmt = branch;
+                self.statement(stmt);
+            },
+            Stmt::ReturnStmt { keyword: _, value, span: _ } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            },
+            Stmt::WhileStmt { condition, body, increment, label: _, span: _ } => {
+                self.expression(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+            },
+            Stmt::Var { name: _, initialiser, span: _ } => self.expression(initialiser),
+        }
+    }
+
+    fn function(self: &mut Self, body: &mut Rc<[Stmt]>) {
+        // the interpreter only shares the body once it runs
+        let body = Rc::get_mut(body).expect("function body shared before optimising");
+        for stmt in body.iter_mut() {
+            self.statement(stmt);
+        }
+    }
+
+    fn expression(self: &mut Self, expr: &mut Expr) {
+        match expr {
+            Expr::Assignment { name: _, value, slot: _, span: _ } => self.expression(value),
+            Expr::Binary { left, right, operator, span } => {
+                self.expression(left);
+                self.expression(right);
+                if let (Expr::Literal { value: left, span: _ }, Expr::Literal { value: right, span: _ }) = (&**left, &**right) {
+                    // same message the interpreter would give when it got there
+                    match Expr::binary_op(left, right, operator.token_type) {
+                        Ok(value) => *expr = Expr::Literal { value, span: *span },
+                        Err(msg) => self.warnings.push(format!("{msg} {span}")),
+                    }
+                }
+            },
+            Expr::Call { callee, paren: _, arguments, span: _ } => {
+                self.expression(callee);
+                for argument in arguments.iter_mut() {
+                    self.expression(argument);
+                }
+            },
+            Expr::Get { object, name: _, span: _ } => self.expression(object),
+            Expr::Grouping { expression, span } => {
+                self.expression(expression);
+                if let Expr::Literal { value, span: _ } = &**expression {
+                    *expr = Expr::Literal { value: value.clone(), span: *span };
+                }
+            },
+            Expr::Interpolation { parts, span: _ } => {
+                for part in parts.iter_mut() {
+                    self.expression(part);
+                }
+            },
+            Expr::Lambda { params: _, body, span: _ } => self.function(body),
+            Expr::Literal { .. } => (),
+            Expr::Logical { left, right, operator, span } => {
+                self.expression(left);
+                self.expression(right);
+                let Expr::Literal { value: left_value, span: _ } = &**left else { return; };
+                let left_true = left_value.is_truthy() == LiteralValue::True;
+                match (operator.token_type, &**right) {
+                    // `or` gives back the left side when it's true, the right one otherwise
+                    (TokenType::Or, _) if left_true => *expr = Expr::Literal { value: left_value.clone(), span: *span },
+                    (TokenType::Or, _) => *expr = std::mem::replace(right.as_mut(), Expr::Literal { value: LiteralValue::Non, span: *span }),
+                    // `and` evaluates both sides so only two literals can go
+                    (TokenType::And, Expr::Literal { value: right_value, span: _ }) => {
+                        let right_true = right_value.is_truthy() == LiteralValue::True;
+                        *expr = Expr::Literal { value: LiteralValue::from_bool(left_true && right_true), span: *span };
+                    },
+                    _ => (),
+                }
+            },
+            Expr::Set { object, name: _, value, operator: _, span: _ } => {
+                self.expression(value);
+                self.expression(object);
+            },
+            Expr::Super { .. } | Expr::This { .. } | Expr::Variable { .. } => (),
+            Expr::Unary { operator, val, span } => {
+                self.expression(val);
+                if let Expr::Literal { value, span: _ } = &**val {
+                    match Expr::unary_op(value, operator.token_type) {
+                        Ok(value) => *expr = Expr::Literal { value, span: *span },
+                        Err(msg) => self.warnings.push(format!("{msg} {span}")),
+                    }
+                }
+            },
+            // the target stays a variable or a field
+            Expr::Update { target, operator: _, prefix: _, span: _ } => {
+                if let Expr::Get { object, name: _, span: _ } = target.as_mut() {
+                    self.expression(object);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digit::DigitType;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+
+    fn optimised(source: &str) -> (Vec<Stmt>, Vec<String>) {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut stmts = Parser::new(&tokens).parse().unwrap();
+        Resolver::new().resolve(&mut stmts).unwrap();
+        let warnings = Optimiser::new().optimise(&mut stmts);
+        (stmts, warnings)
+    }
+
+    #[test]
+    fn literals_are_folded() {
+        let source = "show 60 * 60 * 24; show (\"a\" + \"b\"); show -(2 + 3); show 1 < 2 and 3 < 4;";
+        let (stmts, warnings) = optimised(source);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        let shown = stmts.iter().map(|stmt| match stmt {
+            Stmt::Print { expression: Expr::Literal { value, span: _ }, span: _ } => value.clone(),
+            _ => panic!("not folded in {source:?}"),
+        }).collect::<Vec<_>>();
+        assert_eq!(shown[0], LiteralValue::NumberValue(DigitType::u32(86400)));
+        assert_eq!(shown[1].to_string(), "ab");
+        assert_eq!(shown[2], LiteralValue::NumberValue(DigitType::i8(-5)));
+        assert_eq!(shown[3], LiteralValue::True);
+    }
+
+    #[test]
+    fn only_the_branch_that_runs_is_left() {
+        let (stmts, _) = optimised("if (1 > 2) { show 1; } else { show 2; } if (false) { show 3; } var x = 1; if (x) { show 4; }");
+        assert!(matches!(&stmts[0], Stmt::Block { statements, span: _ }
+            if matches!(statements[..], [ref stmt] if matches!(**stmt, Stmt::Print { .. }))));
+        assert!(matches!(&stmts[1], Stmt::Block { statements, span: _ } if statements.is_empty()));
+        assert!(matches!(&stmts[3], Stmt::IfStmt { .. }));
+    }
+
+    // it's only an error if it runs, the runtime reports it then
+    #[test]
+    fn failing_literals_are_left_with_a_warning() {
+        let (stmts, warnings) = optimised("func f() { return 1 + non; }\nshow \"ok\";");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].ends_with("[Line 1:19]"), "{warnings:?}");
+        let Stmt::Function { body, .. } = &stmts[0] else { panic!("the function is still there") };
+        assert!(matches!(&body[0], Stmt::ReturnStmt { value: Some(Expr::Binary { .. }), .. }));
+    }
+}
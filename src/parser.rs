@@ -1,4 +1,6 @@
 
+use std::rc::Rc;
+
 use crate::expr::{*, Expr::*};
 use crate::literals::LiteralValue;
 use crate::scanner::{Token, TokenType::{self,*}};
@@ -14,120 +16,311 @@ use crate::stmt::Stmt;
 //     }
 // };
 
-pub struct Parser {
-    tokens: Vec<Token>,
+// borrows the scanner's tokens, only the ones that end up in the tree get cloned
+pub struct Parser<'a> {
+    tokens: &'a [Token],
     current: usize,
+    errors: Vec<String>,
+    // `{` thrown away while synchronising, their `}` shouldnt be reported again
+    skipped_braces: usize,
+    // labels of the loops around what's being parsed, `None` for unlabeled ones
+    loops: Vec<Option<Rc<str>>>,
+    // what `this`, `super` and `return` are allowed to do depends on these
+    current_class: ClassKind,
+    current_function: Option<FunctionKind>,
 }
 
-#[derive(Debug)]
+// after this many syntax errors the rest is most likely noise
+const MAX_ERRORS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum FunctionKind {
     Function,
+    Method,
+    Initialiser,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
         Self {
-            tokens: tokens,
+            tokens,
             current: 0,
+            errors: vec![],
+            skipped_braces: 0,
+            loops: vec![],
+            current_class: ClassKind::None,
+            current_function: None,
         }
     }
 
     pub fn parse(self: &mut Self) -> Result<Vec<Stmt>, String> {
         let mut stmts = vec![];
-        let mut errs = vec![];
 
-        while !self.is_at_end() {
-            let stmt = self.declaration();
-            match stmt {
-                Ok(s) => stmts.push(s),
-                Err(msg) => errs.push(msg),
+        while !self.is_at_end() && self.errors.len() < MAX_ERRORS {
+            if self.skipped_braces > 0 && self.match_token(RightBrace) {
+                self.skipped_braces -= 1;
+                continue;
+            }
+            let before = self.current;
+            match self.declaration() {
+                Some(s) => stmts.push(s),
+                // a stray `}` doesnt get skipped by `synchronise`, step over it here
+                None if self.current == before => { self.advance(); },
+                None => (),
             }
         };
 
-        if errs.len() <= 0 { Ok(stmts)}
-        else { Err(errs.join("\n")) }
+        if self.errors.is_empty() { return Ok(stmts); }
+
+        let mut errs = std::mem::take(&mut self.errors);
+        if errs.len() >= MAX_ERRORS {
+            errs.push(format!("Too many syntax errors, stopped after {MAX_ERRORS}."));
+        }
+        Err(errs.join("\n"))
     }
 
-    fn declaration(self: &mut Self) -> Result<Stmt, String> {
+    // one declaration, when it has a syntax error that gets reported and we skip ahead
+    // to where the next statement most likely starts so one typo gives one error
+    fn declaration(self: &mut Self) -> Option<Stmt> {
+        match self.declaration_inner() {
+            Ok(stmt) => Some(stmt),
+            Err(msg) => {
+                if !self.errors.contains(&msg) && self.errors.len() < MAX_ERRORS {
+                    self.errors.push(msg);
+                }
+                self.synchronise();
+                None
+            },
+        }
+    }
+
+    fn declaration_inner(self: &mut Self) -> Result<Stmt, String> {
         if self.match_token(Var) {
             self.var_declaration()
-        } else if self.match_token(Func) {
+        } else if self.match_token(Class) {
+            self.class_declaration()
+        // `func (...)` without a name is an anonymous function in an expression statement
+        } else if self.check(Func) && !self.check_next(LeftParen) {
+            self.advance();
             self.function(FunctionKind::Function)
         } else {
             self.statement()
         }
     }
 
+    // `class Name < Superclass { var field = 0; func method() {...} }`
+    fn class_declaration(self: &mut Self) -> Result<Stmt, String> {
+        let keyword = self.previous().span();
+        let name = self.consume(Identifier, "Expected class name.")?.clone();
+
+        let superclass = if self.match_token(Less) {
+            let superclass = self.consume(Identifier, "Expected superclass name.")?;
+            if superclass.lexeme == name.lexeme {
+                return Err(self.error(superclass, "A class cant inherit from itself."));
+            }
+            Some(Variable { span: superclass.span(), name: superclass.clone(), slot: None })
+        } else { None };
+
+        self.consume(LeftBrace, "Expected '{' before class body.")?;
+
+        let kind = if superclass.is_some() { ClassKind::Subclass } else { ClassKind::Class };
+        let enclosing_class = std::mem::replace(&mut self.current_class, kind);
+        let members = self.class_members();
+        self.current_class = enclosing_class;
+        // the body's `{` is already consumed, let its `}` be skipped after synchronising
+        if members.is_err() { self.skipped_braces += 1; }
+        let (fields, methods) = members?;
+
+        let closing = self.consume(RightBrace, "Expected '}' after class body.")?;
+        Ok(Stmt::Class { name, superclass, fields, methods, span: keyword.to(closing.span()) })
+    }
+
+    fn class_members(self: &mut Self) -> Result<(Vec<Stmt>, Vec<Stmt>), String> {
+        let mut fields = vec![];
+        let mut methods = vec![];
+
+        while !self.check(RightBrace) && !self.is_at_end() {
+            if self.match_token(Var) {
+                fields.push(self.var_declaration()?);
+            } else if self.match_token(Func) {
+                methods.push(self.function(FunctionKind::Method)?);
+            } else {
+                return Err(self.error(self.peek(), "Expected 'var' or 'func' in class body."));
+            }
+        }
+        Ok((fields, methods))
+    }
+
     fn function(self: &mut Self, kind: FunctionKind) -> Result<Stmt, String> {
-        let name = self.consume(Identifier, &format!("Expected {kind:?} name."))?;
+        let keyword = self.previous().span();
+        let name = self.consume(Identifier, &format!("Expected {kind:?} name."))?.clone();
+        let kind = if kind == FunctionKind::Method && &*name.lexeme == "init" {
+            FunctionKind::Initialiser
+        } else { kind };
 
         self.consume(LeftParen, &format!("Expected '(' after {kind:?} name."))?;
+        let params = self.parameters()?;
+
+        self.consume(LeftBrace, &format!("Expected '{kind:?}' before function body."))?;
+        let body = self.function_body(kind)?;
+        let span = keyword.to(self.previous().span());
+
+        Ok(Stmt::Function { name, params: params.into(), body: body.into(), span })
+    }
+
+    // `func (a, b) { ... }` or the short `func (a, b) => a + b`
+    fn lambda(self: &mut Self) -> Result<Expr, String> {
+        let keyword = self.previous().span();
+        self.consume(LeftParen, "Expected '(' after 'func'.")?;
+        let params = self.parameters()?;
+
+        let body = if self.match_token(Arrow) {
+            let arrow = self.previous().clone();
+            let value = self.expression()?;
+            let span = value.span();
+            vec![Stmt::ReturnStmt { keyword: arrow, value: Some(value), span }]
+        } else {
+            self.consume(LeftBrace, "Expected '{' or '=>' after anonymous function parameters.")?;
+            self.function_body(FunctionKind::Function)?
+        };
+        let span = keyword.to(self.previous().span());
 
+        Ok(Lambda { params: params.into(), body: body.into(), span })
+    }
+
+    // everything between the parentheses, the `(` is already consumed
+    fn parameters(self: &mut Self) -> Result<Vec<Token>, String> {
         let mut params = vec![];
         if !self.check(RightParen) {
             loop {
-                params.push(self.consume(Identifier, "Expected parameter name.")?);
+                params.push(self.consume(Identifier, "Expected parameter name.")?.clone());
 
                 if params.len() >= 255 {
-                    let loc = self.peek().line_number;
-                    return Err(format!("Cant have more than 255 arguments [Line {loc}]")); // gotta repeat this for better debugging
+                    return Err(self.error(self.peek(), "Cant have more than 255 parameters.")); // gotta repeat this for better debugging
                 }
                 if !self.match_token(Comma) { break; }
             }
         }
 
         self.consume(RightParen, "Expected ')' after parameters.")?;
-
-        self.consume(LeftBrace, &format!("Expected '{kind:?}' before function body."))?;
-        let body = match self.block_statement()? {
-            Stmt::Block { statements } => statements,
+        Ok(params)
+    }
+
+    // the `{` is already consumed
+    fn function_body(self: &mut Self, kind: FunctionKind) -> Result<Vec<Stmt>, String> {
+        // a loop around the declaration cant be broken out of from inside the function
+        let outer_loops = std::mem::take(&mut self.loops);
+        let enclosing_function = self.current_function.replace(kind);
+        let body = self.block_statement();
+        self.loops = outer_loops;
+        self.current_function = enclosing_function;
+        match body? {
+            Stmt::Block { statements, span: _ } => Ok(statements.into_iter().map(|stmt| *stmt).collect()),
             _ => panic!("Block statement parsed something that was not a block"),
-        };
-
-
-        Ok(Stmt::Function { name, params, body })
-
-
+        }
     }
 
     fn var_declaration(self: &mut Self) -> Result<Stmt, String>{
-        let token = self.consume(Identifier, "Expect variable name.")?;
+        let keyword = self.previous().span();
+        let token = self.consume(Identifier, "Expect variable name.")?.clone();
 
         let initialiser = if self.match_token(Equal) {
             self.expression()?
         } else {
-            Literal { value: LiteralValue::Non }
+            Literal { value: LiteralValue::Non, span: token.span() }
         };
 
-        self.consume(Semicolon, "Expect ';' after variable declaration.")?;
+        let semicolon = self.consume(Semicolon, "Expect ';' after variable declaration.")?;
         Ok(Stmt::Var {
             name: token,
-            initialiser
+            initialiser,
+            span: keyword.to(semicolon.span()),
         })
     }
 
     fn statement(self: &mut Self) -> Result<Stmt, String> {
         if self.match_token(If) { self.if_statement() }
-        else if self.match_token(For) { self.for_statement() }
+        else if self.match_token(For) { self.for_statement(None) }
         else if self.match_token(LeftBrace) { self.block_statement() }
         else if self.match_tokens(&[Print, Show]) { self.print_statement() }
         else if self.match_token(Return) { self.return_statement() }
-        else if self.match_token(While) { self.while_statement() }
+        else if self.match_token(While) { self.while_statement(None) }
+        else if self.match_tokens(&[Break, Continue]) { self.loop_jump() }
+        else if self.check(Identifier) && self.check_next(Colon) { self.labeled_loop() }
         else { self.expression_statement() }
     }
 
+    // `outer: while (...) {...}`
+    fn labeled_loop(self: &mut Self) -> Result<Stmt, String> {
+        let label = self.advance().clone();
+        self.consume(Colon, "Expected ':' after loop label.")?;
+
+        if self.match_token(For) { self.for_statement(Some(label)) }
+        else if self.match_token(While) { self.while_statement(Some(label)) }
+        else {
+            Err(self.error(self.peek(), &format!("Expected a loop after label '{}'.", label.lexeme)))
+        }
+    }
+
+    // `break;`, `continue;` or either with the label of the loop they're for
+    fn loop_jump(self: &mut Self) -> Result<Stmt, String> {
+        let keyword = self.previous().clone();
+        let label = if self.check(Identifier) { Some(self.advance().clone()) } else { None };
+
+        if self.loops.is_empty() {
+            return Err(self.error(&keyword, &format!("Cant use '{}' outside of a loop.", keyword.lexeme)));
+        }
+        if let Some(label) = &label {
+            if !self.loops.contains(&Some(label.lexeme.clone())) {
+                return Err(self.error(label, &format!("No loop labeled '{}' around this '{}'.", label.lexeme, keyword.lexeme)));
+            }
+        }
+
+        let semicolon = self.consume(Semicolon, &format!("Expected ';' after '{}'.", keyword.lexeme))?;
+        let span = keyword.span().to(semicolon.span());
+        match keyword.token_type {
+            Break => Ok(Stmt::Break { keyword, label, span }),
+            _ => Ok(Stmt::Continue { keyword, label, span }),
+        }
+    }
+
+    // the body of a loop, `break`/`continue` are allowed in it
+    fn loop_body(self: &mut Self, label: &Option<Token>) -> Result<Stmt, String> {
+        self.loops.push(label.as_ref().map(|l| l.lexeme.clone()));
+        let body = self.statement();
+        self.loops.pop();
+        body
+    }
+
     fn return_statement(self: &mut Self) -> Result<Stmt, String> {
-        let keyword = self.previous();
-        let value;
-        if !self.check(Semicolon) {
-            value = Some(self.expression()?);
-        } else { value = None; }
+        let keyword = self.previous().clone();
+        if self.current_function.is_none() {
+            return Err(self.error(&keyword, "Cant return from top-level code."));
+        }
+        let value = if !self.check(Semicolon) {
+            if self.current_function == Some(FunctionKind::Initialiser) {
+                return Err(self.error(&keyword, "Cant return a value from an initialiser."));
+            }
+            Some(self.expression()?)
+        } else { None };
 
-        self.consume(Semicolon, "Expected ';' after return value")?;
-        Ok(Stmt::ReturnStmt { keyword, value })
+        let semicolon = self.consume(Semicolon, "Expected ';' after return value")?;
+        let span = keyword.span().to(semicolon.span());
+        Ok(Stmt::ReturnStmt { keyword, value, span })
     }
 
-    fn for_statement(self: &mut Self) -> Result<Stmt, String> {
+    fn for_statement(self: &mut Self, label: Option<Token>) -> Result<Stmt, String> {
+        let keyword = match &label {
+            Some(label) => label.span(),
+            None => self.previous().span(),
+        };
         self.consume(LeftParen, "Expect '(' after 'for'.")?;
 
         let initialiser = if self.match_token(Semicolon) {
@@ -153,42 +346,42 @@ impl Parser {
         } else { None };
         self.consume(RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
-        if let Some(incr) = increment {
-            body = Stmt::Block {
-                statements: vec![
-                    Box::from(body),
-                    Box::from(Stmt::Expression { expression: incr })
-                ]
-            };
-        }
+        let body = self.loop_body(&label)?;
+        // the desugared nodes all point at the whole for loop
+        let span = keyword.to(body.span());
 
-        let cond;
-        match condition {
-            None => cond = Expr::Literal { value: LiteralValue::True },
-            Some(c) => cond = c,
-        }
-        body = Stmt::WhileStmt { condition: cond, body: Box::new(body) };
+        let cond = match condition {
+            None => Expr::Literal { value: LiteralValue::True, span: keyword },
+            Some(c) => c,
+        };
+        let increment = increment.map(Box::from);
+        let mut body = Stmt::WhileStmt { condition: cond, body: Box::new(body), increment, label, span };
 
         if let Some(init) = initialiser {
-            body = Stmt::Block { statements: vec![Box::from(init), Box::from(body)] };
+            body = Stmt::Block { statements: vec![Box::from(init), Box::from(body)], span };
         }
 
         Ok(body)
         // Ok(Stmt::WhileStmt { condition: condition, body: Box::new(body) })
     }
 
-    fn while_statement(self: &mut Self) -> Result<Stmt, String> {
+    fn while_statement(self: &mut Self, label: Option<Token>) -> Result<Stmt, String> {
+        let keyword = match &label {
+            Some(label) => label.span(),
+            None => self.previous().span(),
+        };
         self.consume(LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(RightParen, "Expect ')' after while condition.")?;
 
-        let body = self.statement()?;
+        let body = self.loop_body(&label)?;
+        let span = keyword.to(body.span());
 
-        Ok(Stmt::WhileStmt { condition, body: Box::new(body) })
+        Ok(Stmt::WhileStmt { condition, body: Box::new(body), increment: None, label, span })
     }
 
     fn if_statement(self: &mut Self) -> Result<Stmt, String> {
+        let keyword = self.previous().span();
         self.consume(LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(RightParen, "Expect ')' after if condition.")?;
@@ -200,36 +393,51 @@ impl Parser {
             Some(Box::from(stmt))
         } else { None };
 
+        let end = match &else_branch {
+            Some(else_stmt) => else_stmt.span(),
+            None => then_branch.span(),
+        };
+
         Ok(Stmt::IfStmt {
             condition,
             then_branch: Box::from(then_branch),
             else_branch,
+            span: keyword.to(end),
         })
     }
 
     fn block_statement(self: &mut Self) -> Result<Stmt, String> {
+        let brace = self.previous().span();
         let mut stmts = vec![];
 
         while !self.check(RightBrace) && !self.is_at_end() {
-            let decl = self.declaration()?;
-            stmts.push(Box::new(decl));
+            if let Some(decl) = self.declaration() {
+                stmts.push(Box::new(decl));
+            }
         }
-        self.consume(RightBrace, "Expect '}' to end the block.")?;
-        Ok(Stmt::Block { statements: stmts })
+        let closing = self.consume(RightBrace, "Expect '}' to end the block.")?;
+        Ok(Stmt::Block { statements: stmts, span: brace.to(closing.span()) })
     }
 
     fn print_statement(self: &mut Self) -> Result<Stmt, String> {
+        let keyword = self.previous().span();
         let value = self.expression()?;
-        self.consume(Semicolon, "Expect ';' after value.")?;
+        let semicolon = self.consume(Semicolon, "Expect ';' after value.")?;
 
-        Ok(Stmt::Print { expression: value })
+        Ok(Stmt::Print { expression: value, span: keyword.to(semicolon.span()) })
     }
 
     fn expression_statement(self: &mut Self) -> Result<Stmt, String> {
         let expr = self.expression()?;
-        self.consume(Semicolon, "Expect ';' after expression.")?;
+        // `{ ...; a + b }`, no `;` right before the end of a block
+        if self.check(RightBrace) {
+            let span = expr.span();
+            return Ok(Stmt::Tail { expression: expr, span });
+        }
+        let semicolon = self.consume(Semicolon, "Expect ';' after expression.")?;
+        let span = expr.span().to(semicolon.span());
 
-        Ok(Stmt::Expression { expression: expr })
+        Ok(Stmt::Expression { expression: expr, span })
     }
 
     fn expression(self: &mut Self) -> Result<Expr, String> {
@@ -244,38 +452,69 @@ impl Parser {
             let value = self.assignment()?;
 
             match expr {
-                Variable { name } => Ok(Assignment {
-                    name, value: Box::from(value)
-                }),
-                _ => panic!("Invalid assignment target: {}", equals_op.to_string()),
+                Variable { name, slot: _, span } => {
+                    let span = span.to(value.span());
+                    Ok(Assignment { name, value: Box::from(value), slot: None, span })
+                },
+                Get { object, name, span } => {
+                    let span = span.to(value.span());
+                    Ok(Set { object, name, value: Box::from(value), operator: None, span })
+                },
+                _ => Err(self.error(equals_op, "Invalid assignment target.")),
+            }
+        }
+        // `x += y` is just `x = x + y`
+        else if self.match_tokens(&[PlusEqual, MinusEqual, StarEqual, SlashEqual, ModuloEqual, PowerEqual]) {
+            let compound_op = self.previous();
+            let value = self.assignment()?;
+
+            match expr {
+                Variable { name, slot: _, span } => {
+                    let span = span.to(value.span());
+                    let operator = Self::compound_operator(compound_op);
+                    let current = Variable { name: name.clone(), slot: None, span: name.span() };
+                    let value = Binary {
+                        left: Box::from(current),
+                        right: Box::from(value),
+                        operator,
+                        span,
+                    };
+                    Ok(Assignment { name, value: Box::from(value), slot: None, span })
+                },
+                Get { object, name, span } => {
+                    let span = span.to(value.span());
+                    let operator = Some(Self::compound_operator(compound_op));
+                    Ok(Set { object, name, value: Box::from(value), operator, span })
+                },
+                _ => Err(self.error(compound_op, "Invalid assignment target.")),
             }
         }
-        // else if self.match_tokens(&[PlusPlus, MinusMinus]) {
-        //     let inc_dec = self.previous();
-
-        //     match expr {
-        //         Variable { name } => {
-        //             let op_expr = Unary {
-        //                 val: Box::new(Variable { name: name.clone() }),
-        //                 operator: inc_dec,
-        //             };
-        //             println!("{}", op_expr.to_string());
-        //             Ok(Assignment { name, value: Box::from(op_expr) })
-        //         },
-        //         _ => panic!("Invalid assignment target: {}", inc_dec.to_string()),
-        //     }
-        // }
         else { Ok(expr) }
     }
 
+    // the plain operator token behind `+=`, `-=`...
+    fn compound_operator(token: &Token) -> Token {
+        let (token_type, lexeme) = match token.token_type {
+            PlusEqual => (Plus, "+"),
+            MinusEqual => (Minus, "-"),
+            StarEqual => (Star, "*"),
+            SlashEqual => (Slash, "/"),
+            ModuloEqual => (Modulo, "%"),
+            PowerEqual => (Power, "^"),
+            t_type => panic!("{t_type} is not a compound assignment operator"),
+        };
+        Token { token_type, lexeme: lexeme.into(), ..token.clone() }
+    }
+
     fn or(self: &mut Self) -> Result<Expr, String> {
         let mut expr = self.and()?;
 
         while self.match_token(Or) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.and()?;
 
-            expr = Logical { left: Box::from(expr), right: Box::from(right), operator }
+            let span = expr.span().to(right.span());
+            expr = Logical { left: Box::from(expr), right: Box::from(right), operator, span }
         };
 
         Ok(expr)
@@ -285,10 +524,11 @@ impl Parser {
         let mut expr = self.equality()?;
 
         while self.match_token(And) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.equality()?;
 
-            expr = Logical { left: Box::from(expr), right: Box::from(right), operator }
+            let span = expr.span().to(right.span());
+            expr = Logical { left: Box::from(expr), right: Box::from(right), operator, span }
         };
 
         Ok(expr)
@@ -297,12 +537,14 @@ impl Parser {
     fn equality(self: &mut Self) -> Result<Expr, String>  {
         let mut expr = self.comparison()?;
         while self.match_tokens(&[BangEqual, EqualEqual]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let rhs = self.comparison()?;
+            let span = expr.span().to(rhs.span());
             expr = Binary {
                 left: Box::from(expr),
                 right: Box::from(rhs),
                 operator,
+                span,
             };
             // matches_eq =  self.match_tokens(&[BangEqual, EqualEqual])
         }
@@ -310,15 +552,87 @@ impl Parser {
     }
 
     fn comparison(self: &mut Self) -> Result<Expr, String>  {
-        let mut expr = self.term()?;
+        let mut expr = self.bit_or()?;
 
         while self.match_tokens(&[Greater, GreaterEqual, Less, LessEqual]) {
-            let operator = self.previous();
-            let rhs = self.term()?;
+            let operator = self.previous().clone();
+            let rhs = self.bit_or()?;
+            let span = expr.span().to(rhs.span());
             expr = Binary {
                 left: Box::from(expr),
                 operator,
                 right: Box::from(rhs),
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    // bitwise sits between comparison and arithmetic like in rust:
+    // `|` < `xor` < `&` < shifts
+    fn bit_or(self: &mut Self) -> Result<Expr, String> {
+        let mut expr = self.bit_xor()?;
+
+        while self.match_token(Pipe) {
+            let operator = self.previous().clone();
+            let rhs = self.bit_xor()?;
+            let span = expr.span().to(rhs.span());
+            expr = Binary {
+                left: Box::from(expr),
+                right: Box::from(rhs),
+                operator,
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn bit_xor(self: &mut Self) -> Result<Expr, String> {
+        let mut expr = self.bit_and()?;
+
+        while self.match_token(Xor) {
+            let operator = self.previous().clone();
+            let rhs = self.bit_and()?;
+            let span = expr.span().to(rhs.span());
+            expr = Binary {
+                left: Box::from(expr),
+                right: Box::from(rhs),
+                operator,
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn bit_and(self: &mut Self) -> Result<Expr, String> {
+        let mut expr = self.shift()?;
+
+        while self.match_token(Ampersand) {
+            let operator = self.previous().clone();
+            let rhs = self.shift()?;
+            let span = expr.span().to(rhs.span());
+            expr = Binary {
+                left: Box::from(expr),
+                right: Box::from(rhs),
+                operator,
+                span,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn shift(self: &mut Self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+
+        while self.match_tokens(&[LessLess, GreaterGreater]) {
+            let operator = self.previous().clone();
+            let rhs = self.term()?;
+            let span = expr.span().to(rhs.span());
+            expr = Binary {
+                left: Box::from(expr),
+                right: Box::from(rhs),
+                operator,
+                span,
             };
         }
         Ok(expr)
@@ -328,12 +642,14 @@ impl Parser {
         let mut expr = self.factor()?;
 
         while self.match_tokens(&[Minus, Plus]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let rhs = self.factor()?;
+            let span = expr.span().to(rhs.span());
             expr = Binary {
                 left: Box::from(expr),
                 right: Box::from(rhs),
                 operator,
+                span,
             };
         }
         Ok(expr)
@@ -343,12 +659,14 @@ impl Parser {
         let mut expr = self.expo()?;
 
         while self.match_tokens(&[Slash, Star]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let rhs = self.expo()?;
+            let span = expr.span().to(rhs.span());
             expr = Binary {
                 left: Box::from(expr),
                 right: Box::from(rhs),
                 operator,
+                span,
             };
         }
         Ok(expr)
@@ -358,12 +676,14 @@ impl Parser {
         let mut expr = self.unary()?;
 
         while self.match_tokens(&[Power, Root/* cube/nth root */, Modulo]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let rhs = self.unary()?;
+            let span = expr.span().to(rhs.span());
             expr = Binary {
                 left: Box::from(expr),
                 right: Box::from(rhs),
                 operator,
+                span,
             };
         }
         Ok(expr)
@@ -371,35 +691,55 @@ impl Parser {
 
 
     fn unary(self: &mut Self) -> Result<Expr, String> {
-        if self.match_tokens(&[Bang, Minus, PlusPlus, MinusMinus, Root/* square root*/]) {
-            let operator = self.previous();
+        if self.match_tokens(&[PlusPlus, MinusMinus]) {
+            let operator = self.previous().clone();
+            let target = self.unary()?;
+            self.update(operator, target, true)
+        }
+        else if self.match_tokens(&[Bang, Minus, Tilde, Root/* square root*/]) {
+            let operator = self.previous().clone();
             let rhs = self.unary()?;
+            let span = operator.span().to(rhs.span());
             Ok(Unary {
                 val: Box::from(rhs),
                 operator,
+                span,
             })
         }
         else {
             // self.call()
             let mut expr = self.call()?;
             if self.match_tokens(&[PlusPlus, MinusMinus]) {
-                let operator = self.previous();
-                expr = Unary {
-                    val: Box::new(expr),
-                    operator,
-                };
+                let operator = self.previous().clone();
+                expr = self.update(operator, expr, false)?;
             }
 
             Ok(expr)
         }
     }
 
+    // `++x`/`x++`, only variables and fields can be incremented
+    fn update(self: &mut Self, operator: Token, target: Expr, prefix: bool) -> Result<Expr, String> {
+        match target {
+            Variable { .. } | Get { .. } => {
+                let span = target.span();
+                let span = if prefix { operator.span().to(span) } else { span.to(operator.span()) };
+                Ok(Update { target: Box::from(target), operator, prefix, span })
+            },
+            _ => Err(self.error(&operator, &format!("Invalid target for '{}'.", operator.lexeme))),
+        }
+    }
+
     fn call(self: &mut Self) -> Result<Expr, String> {
         let mut expr = self.primary()?;
 
         loop {
             if self.match_token(LeftParen) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(Dot) {
+                let name = self.consume(Identifier, "Expected property name after '.'.")?.clone();
+                let span = expr.span().to(name.span());
+                expr = Get { object: Box::from(expr), name, span };
             } else { break; }
         }
 
@@ -413,56 +753,112 @@ impl Parser {
                 arguments.push(self.expression()?);
 
                 if arguments.len() >= 255 {
-                    let loc = self.peek().line_number;
-                    return Err(format!("Cant have more than 255 arguments [Line {loc}]"));
+                    return Err(self.error(self.peek(), "Cant have more than 255 arguments."));
                 }
                 if !self.match_token(Comma) { break; }
             }
         }
 
-        let paren = self.consume(RightParen, "Expected ')' after arguments.")?;
+        let paren = self.consume(RightParen, "Expected ')' after arguments.")?.clone();
+        let span = callee.span().to(paren.span());
 
-        Ok(Expr::Call { callee: Box::from(callee), paren, arguments })
+        Ok(Expr::Call { callee: Box::from(callee), paren, arguments, span })
     }
 
     fn primary(self: &mut Self) -> Result<Expr, String> {
         let token = self.peek();
 
-        let result;
-        match token.token_type {
+        let result = match token.token_type {
             LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
-                self.consume(RightParen, "Expected ')'")?;
-                result = Grouping { expression: Box::from(expr) }
+                let closing = self.consume(RightParen, "Expected ')'")?;
+                Grouping { expression: Box::from(expr), span: token.span().to(closing.span()) }
             },
             False | True | Non | Number | StringLit => {
                 self.advance();
-                result = Literal { value: LiteralValue::from_token(token) }
+                let span = token.span();
+                Literal { value: LiteralValue::from_token(token), span }
             },
             Identifier => {
                 self.advance();
-                result = Variable { name: self.previous() }
+                Variable { name: token.clone(), slot: None, span: token.span() }
             },
-            _ => return Err("Expected expression".to_string()),
-        }
+            StringPart => self.interpolation()?,
+            Func => {
+                self.advance();
+                self.lambda()?
+            },
+            TokenType::This => {
+                self.advance();
+                if self.current_class == ClassKind::None {
+                    return Err(self.error(token, "Cant use 'this' outside of a class."));
+                }
+                Expr::This { keyword: token.clone(), slot: None, span: token.span() }
+            },
+            TokenType::Super => {
+                self.advance();
+                match self.current_class {
+                    ClassKind::None => return Err(self.error(token, "Cant use 'super' outside of a class.")),
+                    ClassKind::Class => return Err(self.error(token, "Cant use 'super' in a class without a superclass.")),
+                    ClassKind::Subclass => (),
+                }
+                self.consume(Dot, "Expected '.' after 'super'.")?;
+                let method = self.consume(Identifier, "Expected superclass method name.")?.clone();
+                let span = token.span().to(method.span());
+                Expr::Super { keyword: token.clone(), method, slot: None, span }
+            },
+            _ => return Err(self.error(token, "Expected expression.")),
+        };
 
         Ok(result)
     }
 
-    fn consume(self: &mut Self, token_type: TokenType, msg: &str) -> Result<Token, String>{
+    // "fib {a} after {n} steps" comes in as
+    // StringPart("fib ") a StringPart(" after ") n StringLit(" steps")
+    fn interpolation(self: &mut Self) -> Result<Expr, String> {
+        let opening = self.peek().span();
+        let mut parts = vec![];
+
+        loop {
+            let text = self.advance();
+            let is_last = text.token_type == StringLit;
+            let span = text.span();
+            let value = LiteralValue::from_token(text);
+            if !matches!(&value, LiteralValue::StringValue(text) if text.is_empty()) {
+                parts.push(Literal { value, span });
+            }
+            if is_last { break; }
+
+            parts.push(self.expression()?);
+
+            if !self.check(StringPart) && !self.check(StringLit) {
+                return Err(self.error(self.peek(), "Expected '}' after interpolated expression."));
+            }
+        }
+
+        Ok(Interpolation { parts, span: opening.to(self.previous().span()) })
+    }
+
+    fn consume(self: &mut Self, token_type: TokenType, msg: &str) -> Result<&'a Token, String>{
         let token = self.peek();
         if token.token_type == token_type {
-            self.advance();
-            let token = self.previous();
-            Ok(token)
+            Ok(self.advance())
         } else {
-            println!("Missing token");
-            Err(msg.to_string())
+            Err(self.error(token, msg))
         }
     }
 
-    fn check(self: &mut Self,t_type: TokenType) -> bool {
+    // "Expect ';' after value. Found 'show' [Line 3:1]"
+    fn error(self: &Self, token: &Token, msg: &str) -> String {
+        let found = match token.token_type {
+            Eof => "end of file".to_string(),
+            _ => format!("'{}'", token.lexeme),
+        };
+        format!("{msg} Found {found} {}", token.span())
+    }
+
+    fn check(self: &Self, t_type: TokenType) -> bool {
         self.peek().token_type == t_type
     }
 
@@ -486,35 +882,95 @@ impl Parser {
         false
     }
 
-    fn advance(self: &mut Self) -> Token {
+    fn advance(self: &mut Self) -> &'a Token {
         if !self.is_at_end() { self.current+=1; }
         self.previous()
     }
 
-    fn peek(self: &mut Self) -> Token {
-        self.tokens[self.current].clone()
+    fn check_next(self: &Self, t_type: TokenType) -> bool {
+        self.tokens.get(self.current + 1).is_some_and(|t| t.token_type == t_type)
+    }
+    fn peek(self: &Self) -> &'a Token {
+        &self.tokens[self.current]
     }
 
-    fn previous(self: &mut Self) -> Token {
-        self.tokens[self.current-1].clone()
+    fn previous(self: &Self) -> &'a Token {
+        &self.tokens[self.current-1]
     }
 
-    fn is_at_end(self: &mut Self) -> bool {
+    fn is_at_end(self: &Self) -> bool {
         self.peek().token_type == Eof
     }
 
+    // skips to the next statement boundary, a `}` is left alone so the block
+    // we are in can still close normally
     fn synchronise(self: &mut Self) {
-        self.advance();
+        if self.check(RightBrace) { return; }
+        self.skip_token();
 
         while !self.is_at_end() {
             if self.previous().token_type == Semicolon {return;}
 
             match self.peek().token_type {
-                Class | Func | Var | For | If | While | Print | Show | Return => return,
+                Class | Func | Var | For | If | While | Print | Show | Return
+                | Break | Continue | RightBrace => return,
                 _ => (),
             }
-            self.advance();
+            self.skip_token();
+        }
+    }
+
+    fn skip_token(self: &mut Self) {
+        if self.advance().token_type == LeftBrace {
+            self.skipped_braces += 1;
         }
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn errors(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        Parser::new(&tokens).parse().unwrap_err()
+    }
+
+    #[test]
+    fn every_statement_with_an_error_gets_reported() {
+        assert_eq!(errors("var = 1;\nshow 1 +;\nvar x = 2;\nshow x"), [
+            "Expect variable name. Found '=' [Line 1:5]",
+            "Expected expression. Found ';' [Line 2:9]",
+            "Expect ';' after value. Found end of file [Line 4:7]",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn it_stops_after_too_many_errors() {
+        let errs = errors(&"var = 1;\n".repeat(MAX_ERRORS + 10));
+        let errs = errs.lines().collect::<Vec<_>>();
+        assert_eq!(errs.len(), MAX_ERRORS + 1);
+        assert_eq!(errs[MAX_ERRORS - 1], format!("Expect variable name. Found '=' [Line {MAX_ERRORS}:5]"));
+        assert_eq!(errs[MAX_ERRORS], format!("Too many syntax errors, stopped after {MAX_ERRORS}."));
+    }
+
+    #[test]
+    fn skipped_braces_dont_throw_off_what_comes_after() {
+        // the `{ ... }` skipped with the broken `if` doesnt close anything or leave a stray `}`
+        assert_eq!(errors("if (x { show 1; }\nvar = 2;\nshow 3;"), [
+            "Expect ')' after if condition. Found '{' [Line 1:7]",
+            "Expect variable name. Found '=' [Line 2:5]",
+        ].join("\n"));
+        assert_eq!(errors("class A { func m( { } }\nvar = 3;"), [
+            "Expected parameter name. Found '{' [Line 1:19]",
+            "Expect variable name. Found '=' [Line 2:5]",
+        ].join("\n"));
+        // a block that never closes still reports what's inside it
+        assert_eq!(errors("{ show 1;\nvar = 2;"), [
+            "Expect variable name. Found '=' [Line 2:5]",
+            "Expect '}' to end the block. Found end of file [Line 2:9]",
+        ].join("\n"));
+    }
+}
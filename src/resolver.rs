@@ -0,0 +1,292 @@
+use std::rc::Rc;
+use std::collections::HashMap;
+
+use crate::environment::Slot;
+use crate::expr::Expr;
+use crate::scanner::{Span, Token};
+use crate::stmt::Stmt;
+
+// goes over the whole program once before it runs, works out for every local variable how many
+// environments up it lives and where in there (the `slot` on the nodes) and reports what's wrong with the scoping
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    errors: Vec<String>,
+}
+
+#[derive(Default)]
+struct Scope {
+    // the slot the name got, they're handed out in the order the interpreter defines them in,
+    // and false while the variable's own initialiser is being resolved
+    names: HashMap<Rc<str>, (usize, bool)>,
+    // names used while this scope was open that were looked up further out,
+    // declaring one of them afterwards means the use meant something else
+    outer_uses: HashMap<Rc<str>, Span>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            errors: vec![],
+        }
+    }
+
+    pub fn resolve(self: &mut Self, stmts: &mut [Stmt]) -> Result<(), String> {
+        for stmt in stmts.iter_mut() {
+            self.statement(stmt);
+        }
+
+        if self.errors.is_empty() { Ok(()) }
+        else { Err(std::mem::take(&mut self.errors).join("\n")) }
+    }
+
+    fn statement(self: &mut Self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Block { statements, span: _ } => {
+                self.begin_scope();
+                for stmt in statements.iter_mut() {
+                    self.statement(stmt);
+                }
+                self.end_scope();
+            },
+            Stmt::Break { .. } | Stmt::Continue { .. } => (),
+            Stmt::Class { name, superclass, fields, methods, span: _ } => {
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    self.expression(superclass);
+                }
+
+                // bound methods and field initialisers see `this` and `super` in one environment
+                self.begin_scope();
+                self.define_name("this");
+                if superclass.is_some() { self.define_name("super"); }
+
+                for field in fields.iter_mut() {
+                    if let Stmt::Var { name: _, initialiser, span: _ } = field {
+                        self.expression(initialiser);
+                    }
+                }
+                for method in methods.iter_mut() {
+                    if let Stmt::Function { name: _, params, body, span: _ } = method {
+                        self.function(params, body);
+                    }
+                }
+                self.end_scope();
+            },
+            Stmt::Expression { expression, span: _ } => self.expression(expression),
+            Stmt::Function { name, params, body, span: _ } => {
+                // declared before the body so it can call itself
+                self.declare(name);
+                self.define(name);
+                self.function(params, body);
+            },
+            Stmt::IfStmt { condition, then_branch, else_branch, span: _ } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            },
+            Stmt::Print { expression, span: _ }
+            | Stmt::Tail { expression, span: _ } => self.expression(expression),
+            Stmt::ReturnStmt { keyword: _, value, span: _ } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            },
+            Stmt::WhileStmt { condition, body, increment, label: _, span: _ } => {
+                self.expression(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+            },
+            Stmt::Var { name, initialiser, span: _ } => {
+                self.declare(name);
+                self.expression(initialiser);
+                self.define(name);
+            },
+        }
+    }
+
+    // parameters and body share one environment when it gets called
+    fn function(self: &mut Self, params: &[Token], body: &mut Rc<[Stmt]>) {
+        // nothing holds on to the body before it gets resolved
+        let body = Rc::get_mut(body).expect("function body shared before resolving");
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        for stmt in body.iter_mut() {
+            self.statement(stmt);
+        }
+        self.end_scope();
+    }
+
+    fn expression(self: &mut Self, expr: &mut Expr) {
+        match expr {
+            Expr::Assignment { name, value, slot, span: _ } => {
+                self.expression(value);
+                *slot = self.resolve_local(name);
+            },
+            Expr::Binary { left, right, operator: _, span: _ }
+            | Expr::Logical { left, right, operator: _, span: _ } => {
+                self.expression(left);
+                self.expression(right);
+            },
+            Expr::Call { callee, paren: _, arguments, span: _ } => {
+                self.expression(callee);
+                for argument in arguments.iter_mut() {
+                    self.expression(argument);
+                }
+            },
+            Expr::Get { object, name: _, span: _ } => self.expression(object),
+            Expr::Grouping { expression, span: _ } => self.expression(expression),
+            Expr::Interpolation { parts, span: _ } => {
+                for part in parts.iter_mut() {
+                    self.expression(part);
+                }
+            },
+            Expr::Lambda { params, body, span: _ } => self.function(params, body),
+            Expr::Literal { .. } => (),
+            Expr::Set { object, name: _, value, operator: _, span: _ } => {
+                self.expression(value);
+                self.expression(object);
+            },
+            Expr::Super { keyword, method: _, slot, span: _ }
+            | Expr::This { keyword, slot, span: _ } => *slot = self.resolve_local(keyword),
+            Expr::Unary { operator: _, val, span: _ } => self.expression(val),
+            Expr::Update { target, operator: _, prefix: _, span: _ } => self.expression(target),
+            Expr::Variable { name, slot, span: _ } => {
+                let in_own_initialiser = self.scopes.last()
+                    .and_then(|scope| scope.names.get(&name.lexeme))
+                    .is_some_and(|(_, defined)| !defined);
+                if in_own_initialiser {
+                    self.error(&format!("Cant read local variable '{}' in its own initialiser.", name.lexeme), name.span());
+                }
+                *slot = self.resolve_local(name);
+            },
+        }
+    }
+
+    // `None` when it's not in any local scope, then it has to be a global
+    fn resolve_local(self: &mut Self, name: &Token) -> Option<Slot> {
+        for (depth, scope) in self.scopes.iter_mut().rev().enumerate() {
+            if let Some((index, _)) = scope.names.get(&name.lexeme) {
+                return Some(Slot { depth, index: *index });
+            }
+            scope.outer_uses.entry(name.lexeme.clone()).or_insert(name.span());
+        }
+        None
+    }
+
+    // nothing is hoisted, so two local functions cant call each other: the first one's body already
+    // looked the second one up further out. declaring it first with `var second;` and assigning it
+    // a lambda after works, both backends see the same variable then
+    fn declare(self: &mut Self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else { return; };
+
+        let error = if scope.names.contains_key(&name.lexeme) {
+            Some(format!("Variable '{}' is already declared in this scope.", name.lexeme))
+        } else {
+            scope.outer_uses.get(&name.lexeme).map(|used| format!(
+                "Variable '{}' is used at {} before it's declared in the same scope.", name.lexeme, used
+            ))
+        };
+        let index = Self::slot_index(scope, &name.lexeme);
+        scope.names.insert(name.lexeme.clone(), (index, false));
+
+        if let Some(msg) = error { self.error(&msg, name.span()); }
+    }
+
+    fn define(self: &mut Self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(self: &mut Self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let index = Self::slot_index(scope, name);
+            scope.names.insert(name.into(), (index, true));
+        }
+    }
+
+    // the next free one, a name being declared again keeps its slot (that's an error anyway)
+    fn slot_index(scope: &Scope, name: &str) -> usize {
+        scope.names.get(name).map_or(scope.names.len(), |(index, _)| *index)
+    }
+
+    fn begin_scope(self: &mut Self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(self: &mut Self) {
+        self.scopes.pop();
+    }
+
+    fn error(self: &mut Self, msg: &str, span: Span) {
+        let msg = format!("{msg} {span}");
+        if !self.errors.contains(&msg) {
+            self.errors.push(msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn resolved(source: &str) -> Result<(), String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut stmts = Parser::new(&tokens).parse().unwrap();
+        Resolver::new().resolve(&mut stmts)
+    }
+
+    #[test]
+    fn declaring_twice_in_one_scope_is_an_error() {
+        assert_eq!(resolved("{ var a = 1; var a = 2; }").unwrap_err(),
+            "Variable 'a' is already declared in this scope. [Line 1:18]");
+        // globals and shadowing in an inner scope are fine
+        assert!(resolved("var a = 1; var a = 2; { var a = 3; { var a = 4; } }").is_ok());
+    }
+
+    #[test]
+    fn using_an_outer_variable_then_declaring_it_is_an_error() {
+        assert_eq!(resolved("var a = 1; { show a; var a = 2; }").unwrap_err(),
+            "Variable 'a' is used at [Line 1:19] before it's declared in the same scope. [Line 1:26]");
+    }
+
+    #[test]
+    fn reading_a_variable_in_its_own_initialiser_is_an_error() {
+        assert_eq!(resolved("{ var a = a + 1; }").unwrap_err(),
+            "Cant read local variable 'a' in its own initialiser. [Line 1:11]");
+    }
+
+    #[test]
+    fn every_error_is_reported_once() {
+        let errors = resolved("{ var a = 1; var a = 2; var b = b; }").unwrap_err();
+        assert_eq!(errors.lines().count(), 2, "{errors}");
+    }
+
+    #[test]
+    fn local_functions_can_only_call_the_ones_declared_before_them() {
+        // pinned on purpose, see `declare`
+        let mutual = "{
+            func even(n) { if (n == 0) { return true; } return odd(n - 1); }
+            func odd(n) { if (n == 0) { return false; } return even(n - 1); }
+        }";
+        assert_eq!(resolved(mutual).unwrap_err(),
+            "Variable 'odd' is used at [Line 2:64] before it's declared in the same scope. [Line 3:18]");
+
+        assert!(resolved("{ func f(n) { return f(n); } }").is_ok());
+        assert!(resolved("{
+            var odd;
+            func even(n) { if (n == 0) { return true; } return odd(n - 1); }
+            odd = func(n) { if (n == 0) { return false; } return even(n - 1); };
+        }").is_ok());
+    }
+}
@@ -1,12 +1,21 @@
 
 use std::fs;
 use std::io::{self, Write};
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+use std::thread;
 
 use crate::parser::*;
+use crate::resolver::*;
 use crate::scanner::*;
 
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
 use crate::interpreter::*;
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+use crate::optimiser::Optimiser;
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+use crate::stmt::Stmt;
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+use crate::vm::{self, Vm};
 #[cfg(all(feature = "compiler", not(feature = "interpreter")))]
 use crate::compiler::*;
 // use raz::{
@@ -30,43 +39,124 @@ pub fn run_compile(path: &str) -> Result<(), String>{
 fn runc(compiler: &mut Compiler, path: &str, contents: &str) -> Result<(), String> {
     let mut scanner = Scanner::new(contents);
     let tokens = scanner.scan_tokens()?;
-    let mut parser = Parser::new(tokens);
-    let stmts = parser.parse()?;
+    let mut parser = Parser::new(&tokens);
+    let mut stmts = parser.parse()?;
+    Resolver::new().resolve(&mut stmts)?;
     compiler.compile(path, stmts.iter().collect())?; // so i guess this will be similar to as the Interpreter `interpret` but in there I just write to an .asm file?
 
     Ok(())
 }
 
-// realised that the interpreter is being passed because of mostly `run_prompt` which needs a sort of environment initialised before of it happening
-// kinda might not have a cli version i just realised follow some tutorial they did it but now after going consiousness and having my own opinion i dont like the cli
+// which of the two runs the program, both get the same parsed and resolved statements
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    // walks the statements as they are
+    Interpreter,
+    // compiles them to bytecode first, `raz --vm`
+    Vm,
+}
+
+// how the cli asked for the program to be run
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub backend: Backend,
+    // fold constants before running, `raz --optimise`
+    pub optimise: bool,
+}
+
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
-fn run(interpreter: &mut Interpreter, contents: &str) -> Result<(), String> {
+fn parse(contents: &str, options: Options) -> Result<Vec<Stmt>, String> {
     let mut scanner = Scanner::new(contents);
     let tokens = scanner.scan_tokens()?;
-    let mut parser = Parser::new(tokens);
-    let stmts = parser.parse()?;
+    let mut parser = Parser::new(&tokens);
+    let mut stmts = parser.parse()?;
+    Resolver::new().resolve(&mut stmts)?;
+    if options.optimise {
+        for warning in Optimiser::new().optimise(&mut stmts) {
+            eprintln!("WARNING:\n\t{warning}");
+        }
+    }
+    Ok(stmts)
+}
+
+// realised that the interpreter is being passed because of mostly `run_prompt` which needs a sort of environment initialised before of it happening
+// kinda might not have a cli version i just realised follow some tutorial they did it but now after going consiousness and having my own opinion i dont like the cli
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+fn run(interpreter: &mut Interpreter, contents: &str, options: Options) -> Result<(), String> {
+    let stmts = parse(contents, options)?;
     interpreter.interpret(stmts.iter().collect())?;
 
     Ok(())
 }
 
-// this simple
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
-pub fn run_file(path: &str) -> Result<(), String> {
+fn run_vm(vm: &mut Vm, contents: &str, options: Options) -> Result<(), String> {
+    let stmts = parse(contents, options)?;
+    let script = vm::compile(&stmts)?;
+    vm.interpret(script)
+}
+
+// raz calls recurse on the rust stack, in a release build this fits `call_stack::DEFAULT_MAX_DEPTH`
+// of them. a debug build can take over 100 KiB per call so it runs out of this first, which is a
+// raz stack overflow error too (it's only reserved, the memory gets used as the stack actually grows)
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+const INTERPRETER_STACK_SIZE: usize = 1024 * 1024 * 1024;
+// left over for what runs before the interpreter and for the call that goes over the limit
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+const STACK_HEADROOM: usize = 64 * 1024 * 1024;
+
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+fn on_big_stack(f: impl FnOnce() -> Result<(), String> + Send + 'static) -> Result<(), String> {
+    thread::Builder::new()
+        .name("raz".to_string())
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(f)
+        .map_err(|err| format!("Couldn't start the interpreter thread: {err}"))?
+        .join()
+        .unwrap_or_else(|_| Err("The interpreter crashed".to_string()))
+}
+
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+fn interpreter() -> Interpreter {
     let mut interpreter = Interpreter::new();
+    interpreter.set_stack_size(INTERPRETER_STACK_SIZE - STACK_HEADROOM);
+    interpreter
+}
 
-    match fs::read_to_string(path) {
-        Ok(contents) => run(&mut interpreter, &contents),
-        Err(err) => Err(err.to_string()),
-    }
+// this simple
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+pub fn run_file(path: &str, options: Options) -> Result<(), String> {
+    let path = path.to_string();
+    on_big_stack(move || {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+
+        match options.backend {
+            Backend::Interpreter => run(&mut interpreter(), &contents, options),
+            Backend::Vm => run_vm(&mut Vm::new(), &contents, options),
+        }
+    })
 }
 
 // this might be just changed to some cli thingy maybe local raz package manager and other stuff
 // i dont like the interpreter being there like that
 #[cfg(all(feature = "interpreter", not(feature = "compiler")))]
-pub fn run_prompt() -> Result<(), String> {
-    let mut interpreter = Interpreter::new();
+pub fn run_prompt(options: Options) -> Result<(), String> {
+    on_big_stack(move || match options.backend {
+        Backend::Interpreter => {
+            let mut interpreter = interpreter();
+            prompt(|line| run(&mut interpreter, line, options))
+        },
+        Backend::Vm => {
+            let mut vm = Vm::new();
+            prompt(|line| run_vm(&mut vm, line, options))
+        },
+    })
+}
 
+#[cfg(all(feature = "interpreter", not(feature = "compiler")))]
+fn prompt(mut run_line: impl FnMut(&str) -> Result<(), String>) -> Result<(), String> {
     loop {
         print!("$ => ");
         let mut buffer = String::new();
@@ -85,9 +175,350 @@ pub fn run_prompt() -> Result<(), String> {
             Err(_) => return Err("Couldn't read line".to_string())
         }
 
-        match run(&mut interpreter, &buffer) {
+        match run_line(&buffer) {
             Ok(_) => (),
             Err(msg) => return Err(msg.to_string())
         }
     }
 }
+
+// what the interpreter and the vm both have to do, every test runs the same program on each of them
+#[cfg(all(test, feature = "interpreter", not(feature = "compiler")))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::budget::{Budget, Exceeded};
+
+    const BACKENDS: [Backend; 2] = [Backend::Interpreter, Backend::Vm];
+
+    enum Raz {
+        Interpreter(Interpreter),
+        Vm(Vm),
+    }
+
+    impl Raz {
+        // the interpreter keeps the default stack size, test threads only get 2 MiB
+        fn new(backend: Backend) -> Self {
+            match backend {
+                Backend::Interpreter => Self::Interpreter(Interpreter::new()),
+                Backend::Vm => Self::Vm(Vm::new()),
+            }
+        }
+
+        fn run(self: &mut Self, source: &str) -> Result<(), String> {
+            match self {
+                Self::Interpreter(interpreter) => {
+                    run(interpreter, source, Options { backend: Backend::Interpreter, optimise: false })
+                },
+                Self::Vm(vm) => run_vm(vm, source, Options { backend: Backend::Vm, optimise: false }),
+            }
+        }
+
+        fn set_max_call_depth(self: &mut Self, depth: usize) {
+            match self {
+                Self::Interpreter(interpreter) => interpreter.set_max_call_depth(depth),
+                Self::Vm(vm) => vm.set_max_call_depth(depth),
+            }
+        }
+
+        fn set_budget(self: &mut Self, budget: Budget) {
+            match self {
+                Self::Interpreter(interpreter) => interpreter.set_budget(budget),
+                Self::Vm(vm) => vm.set_budget(budget),
+            }
+        }
+
+        fn take_exceeded(self: &mut Self) -> Option<Exceeded> {
+            match self {
+                Self::Interpreter(interpreter) => interpreter.take_exceeded(),
+                Self::Vm(vm) => vm.take_exceeded(),
+            }
+        }
+
+        fn set_memory_limit(self: &mut Self, limit: Option<usize>) {
+            match self {
+                Self::Interpreter(interpreter) => interpreter.set_memory_limit(limit),
+                Self::Vm(vm) => vm.set_memory_limit(limit),
+            }
+        }
+
+        fn memory_used(self: &Self) -> usize {
+            match self {
+                Self::Interpreter(interpreter) => interpreter.memory_used(),
+                Self::Vm(vm) => vm.memory_used(),
+            }
+        }
+    }
+
+    // runs it on a new one of each and checks they finished the same way, errors included
+    fn on_both(source: &str) -> Result<(), String> {
+        let [interpreted, vm] = BACKENDS.map(|backend| Raz::new(backend).run(source));
+        assert_eq!(interpreted, vm, "the backends disagree on {source}");
+        interpreted
+    }
+
+    #[test]
+    fn functions_outlive_the_scope_they_were_declared_in() {
+        on_both("
+            var kept;
+            { var x = 5; func f() { return x; } kept = f; }
+            func counter() { var n = 0; func inc() { n = n + 1; return n; } return inc; }
+            var next = counter();
+            next();
+            if (kept() + next() != 7) { missing(); }
+        ").unwrap();
+    }
+
+    #[test]
+    fn local_functions_call_each_other_through_a_variable_declared_first() {
+        on_both("
+            {
+                var odd;
+                func even(n) { if (n == 0) { return true; } return odd(n - 1); }
+                odd = func(n) { if (n == 0) { return false; } return even(n - 1); };
+                if (!even(10) or !odd(7) or odd(4)) { missing(); }
+            }
+        ").unwrap();
+    }
+
+    #[test]
+    fn functions_and_methods_are_equal_only_to_themselves() {
+        on_both("
+            var a = func() => 1;
+            var b = func() => 2;
+            var c = a;
+            if (a == b or a != c or clock != clock) { missing(); }
+            func f() {}
+            func g() {}
+            if (f == g or f != f) { missing(); }
+            class A { func m() {} }
+            var x = A();
+            var y = A();
+            var m = x.m;
+            if (x.m == y.m or m != m or A != A) { missing(); }
+        ").unwrap();
+    }
+
+    #[test]
+    fn field_initialisers_can_use_super() {
+        on_both("
+            class A { func hi() { return \"A\"; } }
+            class B < A { var h = super.hi(); }
+            class C < B { var c = super.hi() + this.h; }
+            if (C().c != \"AA\") { missing(); }
+        ").unwrap();
+    }
+
+    #[test]
+    fn runtime_errors_come_back_with_their_traceback() {
+        for backend in BACKENDS {
+            let err = Raz::new(backend).run("func f() { return 1 + non; }\nfunc g() { f(); }\ng();").unwrap_err();
+            assert!(err.contains("in f, called at [Line 2:12]"), "{backend:?}: {err}");
+            assert!(err.contains("in g, called at [Line 3:1]"), "{backend:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn call_errors_point_at_what_was_called_on_both_backends() {
+        assert_eq!(on_both("var x = 5; x();").unwrap_err(), "Number is not callable. [Line 1:12]");
+        assert_eq!(on_both("var x = 5;\nfunc f() { return x(); }\nf();").unwrap_err().lines().next(),
+            Some("Number is not callable. [Line 2:19]"));
+        // the arguments still run first
+        assert_eq!(on_both("var x = 5; x(missing);").unwrap_err(), "Variable \"missing\" has not been declared [Line 1:14]");
+        assert!(on_both("func f(a) {} f();").unwrap_err().ends_with("[Line 1:14]"));
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error_before_the_thread_runs_out_of_stack() {
+        for backend in BACKENDS {
+            let err = Raz::new(backend).run("func f(n) { { { if (true) { return 1 + f(n + 1); } } } } f(0);").unwrap_err();
+            assert!(err.starts_with("Stack overflow"), "{backend:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn call_depth_is_set_per_instance() {
+        let source = "func f(n) { if (n == 0) { return 0; } return 1 + f(n - 1); } f(5);";
+        for backend in BACKENDS {
+            let mut shallow = Raz::new(backend);
+            shallow.set_max_call_depth(3);
+            assert!(shallow.run(source).is_err(), "{backend:?}");
+            assert!(Raz::new(backend).run(source).is_ok(), "{backend:?}");
+        }
+    }
+
+    #[test]
+    fn running_out_of_steps_is_a_budget_error() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            raz.set_budget(Budget { max_steps: Some(1000), deadline: None });
+            let err = raz.run("while (true) {}").unwrap_err();
+            assert!(err.starts_with("Budget exceeded, hit the limit of 1000 steps after 1000 steps"), "{backend:?}: {err}");
+
+            let exceeded = raz.take_exceeded().expect("the budget stopped it");
+            assert_eq!(exceeded.steps, 1000);
+            assert_eq!(raz.take_exceeded(), None);
+        }
+    }
+
+    #[test]
+    fn running_past_the_deadline_is_a_budget_error() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            let deadline = Instant::now() + Duration::from_millis(20);
+            raz.set_budget(Budget { max_steps: None, deadline: Some(deadline) });
+            let err = raz.run("while (true) {}").unwrap_err();
+            assert!(err.starts_with("Budget exceeded, hit the deadline"), "{backend:?}: {err}");
+            assert!(Instant::now() >= deadline);
+
+            let exceeded = raz.take_exceeded().expect("the budget stopped it");
+            assert!(exceeded.elapsed >= Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn the_scripts_own_errors_are_not_budget_errors() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            raz.set_budget(Budget { max_steps: Some(1000), deadline: None });
+            assert!(raz.run("1 + non;").is_err());
+            assert_eq!(raz.take_exceeded(), None, "{backend:?}");
+        }
+    }
+
+    #[test]
+    fn a_new_instance_has_its_own_budget() {
+        for backend in BACKENDS {
+            let mut spent = Raz::new(backend);
+            spent.set_budget(Budget { max_steps: None, deadline: Some(Instant::now()) });
+            assert!(spent.run("while (true) {}").is_err());
+
+            let mut fresh = Raz::new(backend);
+            fresh.run("var i = 0; while (i < 5000) { i = i + 1; }").unwrap();
+            assert_eq!(fresh.take_exceeded(), None, "{backend:?}");
+
+            // and the first one is still out of it
+            assert!(spent.run("while (true) {}").is_err(), "{backend:?}");
+        }
+    }
+
+    #[test]
+    fn growing_past_the_memory_limit_is_an_error() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            raz.set_memory_limit(Some(64 * 1024));
+            let err = raz.run("var s = \"ab\"; while (true) { s = s + s; }").unwrap_err();
+            assert!(err.starts_with("Out of memory"), "{backend:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn local_functions_are_dropped_with_their_scope() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            raz.set_memory_limit(Some(64 * 1024));
+            raz.run("
+                var i = 0;
+                while (i < 10000) {
+                    func f() { return i; }
+                    var g = func() => f();
+                    g();
+                    i = i + 1;
+                }
+            ").unwrap();
+        }
+    }
+
+    // each of these keeps itself alive through a cycle of `Rc`s, over and over
+    #[test]
+    fn cycles_are_given_back_too() {
+        let loops = [
+            // a closure pointing back at the scope it's stored in through the call that made it
+            "func c() { var n = 0; return func() { n = n + 1; return n; }; } var x = c(); x();",
+            // a class pointing back at the scope it was declared in, and an instance of it
+            "class A { var f = 1; func get() { return this.f; } } var a = A(); a.get();",
+            // a local function calling itself, the vm keeps it in its own upvalue
+            "func make() { func fact(n) { if (n <= 1) { return 1; } return n * fact(n - 1); } return fact(5); } make();",
+            // an instance holding itself, and a method bound to it
+            "class B { func init() { this.me = this; this.m = this.get; } func get() { return 1; } } var b = B(); b.m();",
+        ];
+        for body in loops {
+            for backend in BACKENDS {
+                let mut raz = Raz::new(backend);
+                raz.set_memory_limit(Some(256 * 1024));
+                let source = format!("var i = 0; while (i < 20000) {{ {body} i = i + 1; }}");
+                if let Err(err) = raz.run(&source) {
+                    panic!("{backend:?} ran out on {body}: {err}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn what_is_still_used_survives_the_cycles_around_it() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            raz.set_memory_limit(Some(256 * 1024));
+            raz.run("
+                var counter;
+                var fact;
+                var i = 0;
+                while (i < 20000) {
+                    func c() { var n = 0; return func() { n = n + 1; return n; }; }
+                    func f(n) { if (n <= 1) { return 1; } return n * f(n - 1); }
+                    if (i == 0) { counter = c(); fact = f; }
+                    var x = c();
+                    x();
+                    i = i + 1;
+                }
+                counter();
+                if (counter() != 2 or fact(5) != 120) { missing(); }
+            ").unwrap_or_else(|err| panic!("{backend:?}: {err}"));
+        }
+    }
+
+    #[test]
+    fn memory_is_given_back_when_a_scope_ends() {
+        for backend in BACKENDS {
+            let mut raz = Raz::new(backend);
+            let before = raz.memory_used();
+            raz.run("
+                {
+                    var s = \"ab\";
+                    var i = 0;
+                    while (i < 10) { s = s + s; i = i + 1; }
+                    func f() { return s; }
+                    var g = func() => f();
+                    g();
+                }
+            ").unwrap();
+            assert_eq!(raz.memory_used(), before, "{backend:?}");
+
+            raz.run("var kept = \"ab\" + \"cd\";").unwrap();
+            assert!(raz.memory_used() > before, "{backend:?}");
+        }
+    }
+
+    #[test]
+    fn every_instance_counts_its_own_memory() {
+        for backend in BACKENDS {
+            let mut full = Raz::new(backend);
+            full.set_memory_limit(Some(64 * 1024));
+            assert!(full.run("var s = \"ab\"; while (true) { s = s + s; }").is_err());
+
+            let mut other = Raz::new(backend);
+            let before = other.memory_used();
+            other.set_memory_limit(Some(64 * 1024));
+            other.run("var s = \"ab\"; var i = 0; while (i < 10) { s = s + s; i = i + 1; }").unwrap();
+            assert!(other.memory_used() > before, "{backend:?}");
+            assert!(full.memory_used() > 16 * 1024, "{backend:?}");
+
+            // what `full` holds goes back to it, not to whichever one is running when it's dropped
+            let used = other.memory_used();
+            other.run("var t = \"cd\";").unwrap();
+            drop(full);
+            assert!(other.memory_used() >= used, "{backend:?}");
+        }
+    }
+}
@@ -1,30 +1,53 @@
 use std::string::String;
-use std::collections::HashMap;
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
 
 use crate::digit::*;
 //use raz::digit::*;
 
 fn is_digit(ch: char) -> bool {
-    let uch = ch as u8;
-    uch >= '0' as u8 &&
-    uch <= '9' as u8
+    ch.is_ascii_digit()
 }
 
+// identifiers are `_` or any alphabetic char, then alphanumerics. that's the letters and digits
+// of every script (latin, cjk, cyrillic...) but not full XID, std has no tables for it and I dont
+// want a crate just for that. combining marks and connectors other than `_` arent accepted,
+// so `नमस्ते` (virama), `é` written with U+0301 and `a‿b` dont scan as one identifier
 fn is_alpha(ch: char) -> bool {
-    let uch = ch as u8;
-   (uch >= 'a' as u8 && uch <= 'z' as u8) ||
-   (uch >= 'A' as u8 && uch <= 'Z' as u8) ||
-    ch == '_'
+    ch == '_' || ch.is_alphabetic()
 }
 
+// `is_numeric` would let in `²`, `½` and `①` too, only decimal digits (Nd) go in identifiers
 fn is_alph_numeric(ch: char) -> bool {
-    is_alpha(ch) || is_digit(ch)
+    is_alpha(ch) || is_decimal_digit(ch)
+}
+
+// every Nd digit is one of ten in a row from a zero, these are the zeros (unicode 14),
+// digits from scripts added after that are just not accepted
+const DECIMAL_ZEROS: [u32; 66] = [
+    0x30, 0x660, 0x6F0, 0x7C0, 0x966, 0x9E6, 0xA66, 0xAE6, 0xB66, 0xBE6, 0xC66, 0xCE6, 0xD66, 0xDE6,
+    0xE50, 0xED0, 0xF20, 0x1040, 0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50,
+    0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10,
+    0x104A0, 0x10D30, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650,
+    0x116C0, 0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x16A60, 0x16AC0, 0x16B50,
+    0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140, 0x1E2F0, 0x1E950, 0x1FBF0,
+];
+
+fn is_decimal_digit(ch: char) -> bool {
+    let ch = ch as u32;
+    match DECIMAL_ZEROS.binary_search(&ch) {
+        Ok(_) => true,
+        Err(0) => false,
+        Err(next) => ch - DECIMAL_ZEROS[next - 1] < 10,
+    }
 }
 
 fn get_keywords_hashmap() -> HashMap<&'static str, TokenType> {
     HashMap::from([
         ("and", And),
+        ("break", Break),
         ("class", Class),
+        ("continue", Continue),
         ("else", Else),
         ("false", False),
         ("for", For),
@@ -40,16 +63,27 @@ fn get_keywords_hashmap() -> HashMap<&'static str, TokenType> {
         ("true", True),
         ("var", Var),
         ("while", While),
+        ("xor", Xor),
     ])
 }
 
 pub struct Scanner {
     source: String,
     tokens: Vec<Token>,
+    // both are byte offsets into `source`, always sitting on a char boundary
     start: usize,
     current: usize,
+    // line and column (in chars, 1-based) of the cursor and of where the lexeme started
     line: usize,
-    keywords: HashMap<&'static str, TokenType>
+    column: usize,
+    start_line: usize,
+    start_column: usize,
+    // one entry per string we are currently inside a `{...}` of:
+    // (where the string started, how many plain `{` are open in the expression)
+    interpolations: Vec<(Span, usize)>,
+    keywords: HashMap<&'static str, TokenType>,
+    // every identifier's text is stored once, all the tokens naming it share that
+    identifiers: HashSet<Rc<str>>,
 }
 
 impl Scanner {
@@ -60,7 +94,12 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            interpolations: vec![],
             keywords: get_keywords_hashmap(),
+            identifiers: HashSet::new(),
         }
     }
 
@@ -68,29 +107,32 @@ impl Scanner {
         let mut errors = vec![];
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             match self.scan_token() {
                 Ok(_) => (),
                 Err(msg) => errors.push(msg),
             }
         }
+        if let Some((span, _)) = self.interpolations.pop() {
+            errors.push(format!("Unterminated interpolation in string starting {span}"));
+        }
 
-        self.tokens.push(Token {
-            token_type: Eof,
-            lexeme: "".to_string(),
-            literal: None,
-            line_number: self.line
-        });
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+        self.add_token(Eof);
 
-        if errors.len() > 0 {
+        if !errors.is_empty() {
             let mut joined = "".to_string();
             for error in errors {
                 joined.push_str(&error);
-                joined.push_str("\n");
+                joined.push('\n');
             }
             return Err(joined);
         }
 
-        Ok(self.tokens.clone())
+        Ok(std::mem::take(&mut self.tokens))
     }
 
     fn is_at_end(self: &Self) -> bool {
@@ -102,10 +144,26 @@ impl Scanner {
         match c {
             '(' => self.add_token(LeftParen),
             ')' => self.add_token(RightParen),
-            '{' => self.add_token(LeftBrace),
-            '}' => self.add_token(RightBrace),
+            '{' => {
+                if let Some((_, depth)) = self.interpolations.last_mut() { *depth += 1; }
+                self.add_token(LeftBrace);
+            },
+            '}' => match self.interpolations.last_mut() {
+                // closes the `{` of an interpolation so carry on with the rest of the string
+                Some((opening, 0)) => {
+                    let opening = *opening;
+                    self.interpolations.pop();
+                    self.string_from(opening)?;
+                },
+                Some((_, depth)) => {
+                    *depth -= 1;
+                    self.add_token(RightBrace);
+                },
+                None => self.add_token(RightBrace),
+            },
             ',' => self.add_token(Comma),
             '.' => self.add_token(Dot),
+            ':' => self.add_token(Colon),
             ';' => self.add_token(Semicolon),
 
             '-' => {
@@ -124,7 +182,11 @@ impl Scanner {
                 } else { Plus };
                 self.add_token(token);
             },
-            '*' => self.add_token(Star),
+            '*' => {
+                let token = if self.match_token('=')
+                    { StarEqual } else { Star };
+                self.add_token(token);
+            },
             '/' => {
                 if self.match_token('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
@@ -133,10 +195,19 @@ impl Scanner {
                 }
                 else if self.match_token('*') { self.block_comment()?; }
                 else if self.match_token('^') { self.add_token(Root); }
+                else if self.match_token('=') { self.add_token(SlashEqual); }
                 else { self.add_token(Slash); }
            },
-            '^' => self.add_token(Power),
-            '%' => self.add_token(Modulo),
+            '^' => {
+                let token = if self.match_token('=')
+                    { PowerEqual } else { Power };
+                self.add_token(token);
+            },
+            '%' => {
+                let token = if self.match_token('=')
+                    { ModuloEqual } else { Modulo };
+                self.add_token(token);
+            },
 
             '!' => {
                 let token = if self.match_token('=')
@@ -144,32 +215,44 @@ impl Scanner {
                 self.add_token(token);
             },
             '=' => {
-                let token = if self.match_token('=')
-                    { EqualEqual } else { Equal };
+                let token = if self.match_token('=') { EqualEqual }
+                    else if self.match_token('>') { Arrow }
+                    else { Equal };
                 self.add_token(token);
             },
             '<' => {
-                let token = if self.match_token('=')
-                    { LessEqual } else { Less };
+                let token = if self.match_token('=') { LessEqual }
+                    else if self.match_token('<') { LessLess }
+                    else { Less };
                 self.add_token(token);
             },
             '>' => {
-                let token = if self.match_token('=')
-                    { GreaterEqual } else { Greater };
+                let token = if self.match_token('=') { GreaterEqual }
+                    else if self.match_token('>') { GreaterGreater }
+                    else { Greater };
                 self.add_token(token);
             },
 
-            ' ' | '\r' | '\t' => {},
-            '\n' => self.line+=1,
-            '"' => self.string()?,
+            ' ' | '\r' | '\t' | '\n' => {},
+            c if c.is_whitespace() || c == '\u{feff}' => {}, // unicode spaces and the BOM
+            '"' => self.string_from(self.span())?,
 
-            '&' => if self.match_token('&') { self.add_token(And); },
-            '|' => if self.match_token('|') { self.add_token(Or); },
+            '&' => {
+                let token = if self.match_token('&')
+                    { And } else { Ampersand };
+                self.add_token(token);
+            },
+            '|' => {
+                let token = if self.match_token('|')
+                    { Or } else { Pipe };
+                self.add_token(token);
+            },
+            '~' => self.add_token(Tilde),
 
             c => {
                 if is_digit(c) { self.number()?; }
                 else if is_alpha(c) { self.identifier()?; }
-                else { return Err(format!("Unrecognized token at line {}: {}", self.line, c)); }
+                else { return Err(format!("Unrecognized token '{c}' {}", self.span())); }
             },
         }
         // println!("{}", c);
@@ -177,11 +260,10 @@ impl Scanner {
     }
 
     fn block_comment(self: &mut Self) -> Result<(), String>{
-        while self.peek() != '*' && self.peek_next() != '/' && !self.is_at_end() {
-            if self.peek() == '\n' { self.line+=1; }
+        while (self.peek() != '*' || self.peek_next() != '/') && !self.is_at_end() {
             self.advance();
         }
-        if self.is_at_end() { return Err("Unterminated block comment".to_string()); }
+        if self.is_at_end() { return Err(format!("Unterminated block comment starting {}", self.span())); }
 
         self.advance();
         self.advance();
@@ -189,45 +271,125 @@ impl Scanner {
         Ok(())
     }
 
-    fn string(self: &mut Self) -> Result<(), String>{
+    // scans up to the closing quote or up to the next `{` of an interpolation,
+    // in which case the text so far becomes a `StringPart` and the expression
+    // is scanned as normal tokens until its matching `}` brings us back here
+    fn string_from(self: &mut Self, opening: Span) -> Result<(), String>{
+        let mut value = String::new();
+        // keep going after a bad escape so the rest of the string isnt scanned as code
+        let mut escape_error = None;
+
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' { self.line+=1; }
-            self.advance();
+            let at = self.cursor();
+            match self.advance() {
+                '\\' => match self.escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(msg) => { escape_error.get_or_insert(format!("{msg} {at}")); },
+                },
+                '{' => {
+                    if let Some(msg) = escape_error { return Err(msg); }
+                    self.interpolations.push((opening, 0));
+                    self.add_token_lit(StringPart, Some(StringValue(value.into())));
+                    return Ok(());
+                },
+                ch => value.push(ch),
+            }
         }
 
-        if self.is_at_end() { return Err("Unterminated string".to_string()); }
+        if self.is_at_end() { return Err(format!("Unterminated string starting {opening}")); }
 
         self.advance();
 
-        let value = &self.source[self.start+1..self.current-1];
+        if let Some(msg) = escape_error { return Err(msg); }
 
-        self.add_token_lit(StringLit, Some(StringValue(value.to_string())));
+        self.add_token_lit(StringLit, Some(StringValue(value.into())));
 
         Ok(())
     }
 
+    // called right after the backslash
+    fn escape(self: &mut Self) -> Result<char, String> {
+        if self.is_at_end() { return Err("Unterminated escape sequence".to_string()); }
+
+        match self.advance() {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '{' => Ok('{'),
+            '}' => Ok('}'),
+            'u' => self.unicode_escape(),
+            '\n' => Err("Unknown escape sequence '\\' followed by a newline".to_string()),
+            ch => Err(format!("Unknown escape sequence '\\{ch}'")),
+        }
+    }
+
+    // `\u{XXXX}`, 1 to 6 hex digits
+    fn unicode_escape(self: &mut Self) -> Result<char, String> {
+        if !self.match_token('{') {
+            return Err("Expected '{' after '\\u'".to_string());
+        }
+
+        let mut digits = String::new();
+        while self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+
+        if !self.match_token('}') {
+            return Err(format!("Expected '}}' to close '\\u{{{digits}'"));
+        }
+        if digits.is_empty() || digits.len() > 6 {
+            return Err(format!("Unicode escape '\\u{{{digits}}}' must have 1 to 6 hex digits"));
+        }
+
+        u32::from_str_radix(&digits, 16).ok()
+            .and_then(char::from_u32)
+            .ok_or(format!("Unicode escape '\\u{{{digits}}}' is not a valid character"))
+    }
+
+    // only finds where the literal ends, `DigitType::from_string` makes sense of it
     fn number(self: &mut Self) -> Result<(), String>{
-        while is_digit(self.peek()) { self.advance(); }
-        if self.peek() == '.' && is_digit(self.peek_next()) {
+        let first = self.source[self.start..].chars().next();
+        if first == Some('0') && matches!(self.peek(), 'x' | 'b' | 'o') {
+            // 0x, 0b and 0o, digits and suffix in one go
             self.advance();
-            while is_digit(self.peek()) { self.advance(); }
+            while is_alph_numeric(self.peek()) { self.advance(); }
+        } else {
+            while is_digit(self.peek()) || self.peek() == '_' { self.advance(); }
+            if self.peek() == '.' && is_digit(self.peek_next()) {
+                self.advance();
+                while is_digit(self.peek()) || self.peek() == '_' { self.advance(); }
+            }
+            if matches!(self.peek(), 'e' | 'E') && self.exponent_follows() {
+                self.advance();
+                if !self.match_token('+') { self.match_token('-'); }
+                while is_digit(self.peek()) || self.peek() == '_' { self.advance(); }
+            }
+            // type suffix
+            while is_alph_numeric(self.peek()) { self.advance(); }
         }
 
         let sub_string = &self.source[self.start..self.current];
-        let number_value = NumberValue(DigitType::from_string(sub_string.to_string()));
-        self.add_token_lit(Number, Some(number_value));
-        // let value = sub_string.parse::<f64>();
-        // let value = sub_string.parse::<u32>();
-        // match value {
-        //     Ok(value) => self.add_token_lit(Number, Some(
-        //         NumberValue(DigitType::from_string(sub_string.to_string())))
-        //     ),
-        //     Err(_) => return Err(format!("Could not parse number: {sub_string}"))
-        // }
+        match DigitType::from_string(sub_string) {
+            Ok(value) => self.add_token_lit(Number, Some(NumberValue(value))),
+            Err(msg) => return Err(format!("{msg} {}", self.span())),
+        }
 
         Ok(())
     }
 
+    // `1e5`, `1e-5` and `1e+5` but not `1else`
+    fn exponent_follows(self: &Self) -> bool {
+        let mut after = self.source[self.current..].chars().skip(1);
+        match after.next() {
+            Some('+' | '-') => after.next().is_some_and(is_digit),
+            Some(ch) => is_digit(ch),
+            None => false,
+        }
+    }
+
     fn identifier(self: &mut Self) -> Result<(), String> {
         while is_alph_numeric(self.peek()) { self.advance(); }
 
@@ -240,32 +402,59 @@ impl Scanner {
         Ok(())
     }
 
+    // the cursor only ever moves by whole chars so slicing from `current` is always valid
+    // and peeking is O(1) instead of walking the source with `chars().nth()`
     fn peek(self: &Self) -> char {
-        if self.is_at_end() { return '\0'; }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(self: &Self) -> char {
-        if self.current+1 >= self.source.len() { return '\0'; }
-        return self.source.chars().nth(self.current+1).unwrap();
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn match_token(self: &mut Self, expected: char) -> bool {
-        if self.is_at_end() { return false; }
-        if self.source.chars().nth(self.current).unwrap() != expected {
-            return false;
+        if self.is_at_end() || self.peek() != expected {
+            false
         } else {
-            self.current+=1;
-            return true;
+            self.advance();
+            true
         }
     }
 
     fn advance(self: &mut Self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap();
-        self.current += 1;
+        let c = self.peek();
+        self.current += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         c
     }
 
+    // where the current lexeme is in the source
+    fn span(self: &Self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
+            line: self.start_line,
+            column: self.start_column,
+        }
+    }
+
+    // the single char under the cursor
+    fn cursor(self: &Self) -> Span {
+        Span {
+            start: self.current,
+            end: self.current + self.peek().len_utf8(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
     fn add_token(self: &mut Self, token_type: TokenType) {
         self.add_token_lit(token_type, None);
     }
@@ -275,18 +464,27 @@ impl Scanner {
         // let _= self.source[self.start..self.current]
         //     .chars()
         //     .map(|ch| text.push(ch));
-        let text = self.source[self.start..self.current].to_string();
+        let text = &self.source[self.start..self.current];
+        let lexeme = match (token_type, self.identifiers.get(text)) {
+            (Identifier, Some(interned)) => interned.clone(),
+            (Identifier, None) => {
+                let interned: Rc<str> = Rc::from(text);
+                self.identifiers.insert(interned.clone());
+                interned
+            },
+            _ => Rc::from(text),
+        };
 
         self.tokens.push(Token {
             token_type,
-            lexeme: text,
+            lexeme,
             literal,
-            line_number: self.line,
+            line_number: self.start_line,
+            column: self.start_column,
+            start: self.start,
+            end: self.current,
         });
     }
-
-
-
 }
 
 #[allow(dead_code)]
@@ -294,22 +492,28 @@ impl Scanner {
 pub enum TokenType {
     // Single-character tokens.
     LeftParen, RightParen, LeftBrace, RightBrace,
-    Semicolon, Comma, Dot,
+    Semicolon, Colon, Comma, Dot,
     // SAMDEB ->
     Minus, Plus, Star, Slash, Power, Root,
     Modulo,
     MinusMinus, PlusPlus,
     // Eq
     PlusEqual, MinusEqual,
-    Equal, EqualEqual,
+    StarEqual, SlashEqual, ModuloEqual, PowerEqual,
+    Equal, EqualEqual, Arrow,
     Greater, GreaterEqual,
     Less, LessEqual,
     Bang, BangEqual,
+    // Bitwise
+    Ampersand, Pipe, Tilde,
+    LessLess, GreaterGreater,
     // Literals.
     Identifier, StringLit, Number,
+    // text of an interpolated string up to a `{`, the last piece is a normal `StringLit`
+    StringPart,
     // Keywords.
-    And, Class, Else, False, Func, For, If, Non, Or,
-    Print, Show, Return, Super, This, True, Var, While,
+    And, Break, Class, Continue, Else, False, Func, For, If, Non, Or,
+    Print, Show, Return, Super, This, True, Var, While, Xor,
     Eof
 }
 
@@ -324,31 +528,73 @@ impl std::fmt::Display for TokenType {
 #[derive(Debug, Clone)]
 pub enum LiteralValue {
     NumberValue(DigitType),
-    StringValue(String),
+    StringValue(Rc<str>),
 }
 use LiteralValue::*;
 
+// a stretch of the source, byte offsets for slicing plus the line/column
+// (in chars, both starting at 1) of where it starts for the error messages
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    // from the start of this one to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[Line {}:{}]", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Rc<str>,
     pub literal: Option<LiteralValue>,
     pub line_number: usize,
+    pub column: usize,
+    // byte offsets into the source
+    pub start: usize,
+    pub end: usize,
 }
 
 #[allow(dead_code)]
 impl Token {
     pub fn new(
         token_type: TokenType,
-        lexeme: String,
+        lexeme: Rc<str>,
         literal: Option<LiteralValue>,
-        line_number: usize
+        span: Span,
     ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
-            line_number
+            line_number: span.line,
+            column: span.column,
+            start: span.start,
+            end: span.end,
+        }
+    }
+
+    pub fn span(self: &Self) -> Span {
+        Span {
+            start: self.start,
+            end: self.end,
+            line: self.line_number,
+            column: self.column,
         }
     }
 
@@ -356,3 +602,40 @@ impl Token {
         format!("{} {} {:?}", self.token_type, self.lexeme, self.literal)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers(source: &str) -> Result<Vec<String>, String> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        Ok(tokens.iter().filter(|token| token.token_type == Identifier).map(|token| token.lexeme.to_string()).collect())
+    }
+
+    #[test]
+    fn letters_and_digits_of_any_script_make_one_identifier() {
+        for name in ["_", "_a1", "café", "変数", "переменная", "ΔΣ", "x٣", "x१२", "x１", "Ⅻ", "नमन"] {
+            assert_eq!(identifiers(name), Ok(vec![name.to_string()]), "{name}");
+        }
+    }
+
+    #[test]
+    fn other_numbers_and_marks_dont_go_in_identifiers() {
+        for name in ["x²", "x½", "x①", "x₀", "²", "नमस्ते", "a\u{301}", "a‿b"] {
+            assert!(identifiers(name).is_err(), "{name}");
+        }
+        // digits still cant start one
+        assert!(identifiers("1x").is_err());
+        assert!(identifiers("٣x").is_err());
+    }
+
+    #[test]
+    fn the_decimal_zeros_start_ten_digits_each() {
+        assert!(DECIMAL_ZEROS.is_sorted());
+        for zero in DECIMAL_ZEROS {
+            for digit in zero..zero + 10 {
+                assert!(char::from_u32(digit).is_some_and(char::is_numeric), "{digit:#X}");
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 
+use std::rc::Rc;
+
 use crate::expr::Expr;
-use crate::scanner::Token;
+use crate::scanner::{Span, Token};
 // use raz::{
 //     expr::Expr,
 //     scanner::Token
@@ -8,58 +10,110 @@ use crate::scanner::Token;
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
-    Block { statements: Vec<Box<Stmt>>},
-    Expression { expression: Expr },
+    Block { statements: Vec<Box<Stmt>>, span: Span },
+    // `label` is the loop's name when it was given one, `break outer;`
+    Break { keyword: Token, label: Option<Token>, span: Span },
+    Continue { keyword: Token, label: Option<Token>, span: Span },
+    Class {
+        name: Token,
+        // a `Variable` naming the class after `<`
+        superclass: Option<Expr>,
+        // `Var` declarations
+        fields: Vec<Stmt>,
+        // `Function` declarations
+        methods: Vec<Stmt>,
+        span: Span,
+    },
+    Expression { expression: Expr, span: Span },
+    // params and body are shared with every callable made from the declaration
     Function {
         name: Token,
-        params: Vec<Token>,
-        body: Vec<Box<Stmt>>
+        params: Rc<[Token]>,
+        body: Rc<[Stmt]>,
+        span: Span,
     },
     IfStmt { 
         condition: Expr,
         then_branch: Box<Stmt>,
-        else_branch: Option<Box<Stmt>>
+        else_branch: Option<Box<Stmt>>,
+        span: Span,
     },
-    Print { expression: Expr },
+    Print { expression: Expr, span: Span },
+    // the last expression of a block without a `;`, it's what the block evaluates to
+    Tail { expression: Expr, span: Span },
     ReturnStmt { 
         keyword: Token,
-        value: Option<Expr>
+        value: Option<Expr>,
+        span: Span,
     },
     WhileStmt { 
         condition: Expr,
-        body: Box<Stmt>
+        body: Box<Stmt>,
+        // the increment of a `for` loop, kept apart so `continue` still runs it
+        increment: Option<Box<Expr>>,
+        label: Option<Token>,
+        span: Span,
     },
     Var {
         name: Token,
-        initialiser: Expr
+        initialiser: Expr,
+        span: Span,
     },
 }
 impl Stmt {
+    pub fn span(self: &Self) -> Span {
+        use Stmt::*;
+        match self {
+            Block { span, .. }
+            | Break { span, .. }
+            | Continue { span, .. }
+            | Class { span, .. }
+            | Expression { span, .. }
+            | Function { span, .. }
+            | IfStmt { span, .. }
+            | Print { span, .. }
+            | Tail { span, .. }
+            | ReturnStmt { span, .. }
+            | WhileStmt { span, .. }
+            | Var { span, .. } => *span,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn to_string(self: &mut Self) -> String {
         use Stmt::*;
         match self {
-            Expression { expression } => expression.to_string(),
-            Print { expression } => format!("(print {})", expression.to_string()),
-            Var { name, initialiser: _ } => format!("(var {})", name.lexeme),
-            Block { statements } => format!(
+            Expression { expression, span: _ } => expression.to_string(),
+            Print { expression, span: _ } => format!("(print {})", expression.to_string()),
+            Tail { expression, span: _ } => format!("(tail {})", expression.to_string()),
+            Var { name, initialiser: _, span: _ } => format!("(var {})", name.lexeme),
+            Block { statements, span: _ } => format!(
                 "(block {})",
                 statements.iter_mut().map(
                     |stmt| stmt.to_string()
                 ).collect::<String>()
             ),
+            Break { keyword: _, label, span: _ } => match label {
+                Some(label) => format!("(break {})", label.lexeme),
+                None => "(break)".to_string(),
+            },
+            Continue { keyword: _, label, span: _ } => match label {
+                Some(label) => format!("(continue {})", label.lexeme),
+                None => "(continue)".to_string(),
+            },
+            Class { name, superclass: _, fields: _, methods: _, span: _ } => format!("(class {})", name.lexeme),
             // TODO those, but unusable except when testing
             // started debugging in interactive mode however, so..
-            IfStmt { condition: _, then_branch: _, else_branch: _ } => {
+            IfStmt { condition: _, then_branch: _, else_branch: _, span: _ } => {
                 todo!();
             },
-            WhileStmt { condition: _, body: _ } => {
+            WhileStmt { condition: _, body: _, increment: _, label: _, span: _ } => {
                 todo!();
             },
-            Function { name: _, params: _, body: _} => {
+            Function { name: _, params: _, body: _, span: _ } => {
                 todo!();
             },
-            ReturnStmt { keyword: _, value: _} => {
+            ReturnStmt { keyword: _, value: _, span: _ } => {
                 todo!();
             }
         }
@@ -0,0 +1,107 @@
+use std::rc::Rc;
+
+use crate::literals::LiteralValue;
+use crate::scanner::{Span, TokenType};
+
+// one instruction, the operands are indexes into the chunk's tables or into the stack
+// kept `Copy` and small so the vm can just read it out of the chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Pop,
+    Dup,
+    // locals are stack slots counted from the start of the function's frame
+    GetLocal(u32),
+    SetLocal(u32),
+    // variables a closure captured from the functions around it
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    // globals go by name, the operand is the name's index in `names`
+    DefineGlobal(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    GetProperty(u32),
+    SetProperty(u32),
+    // `obj.name++`, gives back the new value or the old one depending on `prefix`
+    UpdateProperty { name: u32, step: TokenType, prefix: bool },
+    // takes `this` and `super` off the stack and gives back the bound method
+    GetSuper(u32),
+    Binary(TokenType),
+    Unary(TokenType),
+    // both sides get evaluated, same as the interpreter does it
+    And,
+    // joins this many values into one string
+    Interpolate(u32),
+    // jumps go to an absolute index in `code`, the conditional ones leave the condition on the stack
+    Jump(u32),
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    // the operand is the argument count, the callee sits right under the arguments
+    Call(u32),
+    // `return f(...)`, the call replaces the frame of the function returning it
+    TailCall(u32),
+    // makes a closure out of `functions[n]`
+    Closure(u32),
+    // moves the local on top of the stack into the closures that captured it before popping it
+    CloseUpvalue,
+    Return,
+    Print,
+    Class(u32),
+    // `class A < B`, the stack has B under A, A gets popped
+    Inherit,
+    // adds the closure on top of the stack as a method of the class under it
+    Method(u32),
+    // same for the function setting up the fields of new instances
+    Fields,
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    // where in the source each instruction came from, for the errors
+    pub spans: Vec<Span>,
+    pub constants: Vec<LiteralValue>,
+    pub names: Vec<Rc<str>>,
+    // functions declared inside of this one
+    pub functions: Vec<Rc<Function>>,
+}
+
+impl Chunk {
+    pub fn emit(self: &mut Self, op: Op, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    pub fn constant(self: &mut Self, value: LiteralValue) -> u32 {
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    // the same name is only stored once
+    pub fn name(self: &mut Self, name: &str) -> u32 {
+        match self.names.iter().position(|n| &**n == name) {
+            Some(index) => index as u32,
+            None => {
+                self.names.push(name.into());
+                (self.names.len() - 1) as u32
+            },
+        }
+    }
+}
+
+// where a closure gets a captured variable from when it's created
+#[derive(Debug, Clone, Copy)]
+pub struct Capture {
+    // a local of the function right around it, otherwise one of that function's own captures
+    pub is_local: bool,
+    pub index: u32,
+}
+
+// a compiled function, closures of it share this
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub captures: Vec<Capture>,
+}
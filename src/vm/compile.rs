@@ -0,0 +1,533 @@
+use std::rc::Rc;
+
+use crate::digit::DigitType;
+use crate::expr::Expr;
+use crate::literals::LiteralValue;
+use crate::scanner::{Span, Token, TokenType};
+use crate::stmt::Stmt;
+use crate::vm::chunk::{Capture, Chunk, Function, Op};
+
+// turns the resolved statements into one function for the vm to run, the functions
+// declared in them end up in its chunk (and theirs in their chunks...)
+pub fn compile(stmts: &[Stmt]) -> Result<Rc<Function>, String> {
+    let mut compiler = Compiler { functions: vec![] };
+    compiler.begin_function("script", FunctionKind::Script);
+    for stmt in stmts {
+        compiler.statement(stmt, false)?;
+    }
+    let end = stmts.last().map(Stmt::span).unwrap_or_default();
+    Ok(Rc::new(compiler.end_function(end)))
+}
+
+struct Compiler {
+    // the function being compiled is the last one, the ones before it are around it
+    functions: Vec<FunctionState>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    // always gives back `this`
+    Initialiser,
+    // sets up the fields of a new instance
+    Fields,
+}
+
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    // the stack slots of the frame, slot 0 is the callee (or `this` in methods)
+    locals: Vec<Local>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    // a closure uses it, so it has to be moved off the stack when it goes out of scope
+    captured: bool,
+}
+
+struct Loop {
+    label: Option<Rc<str>>,
+    // the scope the loop is in, anything deeper gets dropped when jumping out
+    depth: usize,
+    // jumps to patch once the end of the loop is known
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+enum Variable {
+    Local(u32),
+    Upvalue(u32),
+    Global(u32),
+}
+
+impl Compiler {
+    fn statement(self: &mut Self, stmt: &Stmt, tail: bool) -> Result<(), String> {
+        match stmt {
+            Stmt::Block { statements, span } => {
+                self.begin_scope();
+                let count = statements.len();
+                for (i, stmt) in statements.iter().enumerate() {
+                    self.statement(stmt, tail && i + 1 == count)?;
+                }
+                self.end_scope(*span);
+            },
+            Stmt::Break { keyword: _, label, span } => self.loop_jump(label, *span, true)?,
+            Stmt::Continue { keyword: _, label, span } => self.loop_jump(label, *span, false)?,
+            Stmt::Class { name, superclass, fields, methods, span } => self.class(name, superclass, fields, methods, *span)?,
+            Stmt::Expression { expression, span } => {
+                self.expression(expression)?;
+                self.emit(Op::Pop, *span);
+            },
+            Stmt::Function { name, params, body, span } => {
+                // declared before the body so it can call itself
+                self.declare(&name.lexeme);
+                self.function(&name.lexeme, params, body, FunctionKind::Function, *span)?;
+                self.define(&name.lexeme, *span);
+            },
+            Stmt::IfStmt { condition, then_branch, else_branch, span } => {
+                self.expression(condition)?;
+                let else_jump = self.emit(Op::JumpIfFalse(0), *span);
+                self.emit(Op::Pop, *span);
+                self.statement(then_branch, tail)?;
+                let end_jump = self.emit(Op::Jump(0), *span);
+
+                self.patch(else_jump);
+                self.emit(Op::Pop, *span);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch, tail)?;
+                }
+                self.patch(end_jump);
+            },
+            Stmt::Print { expression, span } => {
+                self.expression(expression)?;
+                self.emit(Op::Print, *span);
+            },
+            Stmt::Tail { expression, span } => {
+                self.expression(expression)?;
+                // only the value the function ends on is given back, the interpreter drops the others too
+                match self.current().kind {
+                    FunctionKind::Script => { self.emit(Op::Pop, *span); },
+                    _ if !tail => { self.emit(Op::Pop, *span); },
+                    FunctionKind::Initialiser => {
+                        self.emit(Op::Pop, *span);
+                        self.emit(Op::GetLocal(0), *span);
+                        self.emit(Op::Return, *span);
+                    },
+                    _ => { self.emit(Op::Return, *span); },
+                }
+            },
+            Stmt::ReturnStmt { keyword: _, value, span } => {
+                match value {
+                    _ if self.current().kind == FunctionKind::Initialiser => { self.emit(Op::GetLocal(0), *span); },
+                    Some(Expr::Call { callee, paren: _, arguments, span: _ }) => {
+                        self.expression(callee)?;
+                        for argument in arguments {
+                            self.expression(argument)?;
+                        }
+                        self.emit(Op::TailCall(arguments.len() as u32), callee.span());
+                        return Ok(());
+                    },
+                    Some(value) => self.expression(value)?,
+                    None => self.literal(LiteralValue::Non, *span),
+                }
+                self.emit(Op::Return, *span);
+            },
+            Stmt::WhileStmt { condition, body, increment, label, span } => {
+                let loop_start = self.current_chunk().code.len() as u32;
+                self.expression(condition)?;
+                let exit_jump = self.emit(Op::JumpIfFalse(0), *span);
+                self.emit(Op::Pop, *span);
+
+                let depth = self.current().scope_depth;
+                self.current().loops.push(Loop {
+                    label: label.as_ref().map(|label| label.lexeme.clone()),
+                    depth,
+                    breaks: vec![],
+                    continues: vec![],
+                });
+                self.statement(body, false)?;
+                let finished = self.current().loops.pop().expect("pushed right before the body");
+
+                for jump in finished.continues {
+                    self.patch(jump);
+                }
+                if let Some(increment) = increment {
+                    self.expression(increment)?;
+                    self.emit(Op::Pop, *span);
+                }
+                self.emit(Op::Jump(loop_start), *span);
+
+                self.patch(exit_jump);
+                self.emit(Op::Pop, *span);
+                for jump in finished.breaks {
+                    self.patch(jump);
+                }
+            },
+            Stmt::Var { name, initialiser, span } => {
+                self.expression(initialiser)?;
+                self.declare(&name.lexeme);
+                self.define(&name.lexeme, *span);
+            },
+        }
+        Ok(())
+    }
+
+    fn expression(self: &mut Self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Assignment { name, value, slot: _, span: _ } => {
+                self.expression(value)?;
+                self.set_variable(&name.lexeme, name.span());
+            },
+            Expr::Binary { left, right, operator, span } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit(Op::Binary(operator.token_type), *span);
+            },
+            Expr::Call { callee, paren: _, arguments, span: _ } => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.emit(Op::Call(arguments.len() as u32), callee.span());
+            },
+            Expr::Get { object, name, span: _ } => {
+                self.expression(object)?;
+                let name_index = self.current_chunk().name(&name.lexeme);
+                self.emit(Op::GetProperty(name_index), name.span());
+            },
+            Expr::Grouping { expression, span: _ } => self.expression(expression)?,
+            Expr::Interpolation { parts, span } => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                self.emit(Op::Interpolate(parts.len() as u32), *span);
+            },
+            Expr::Lambda { params, body, span } => self.function("anonymous", params, body, FunctionKind::Function, *span)?,
+            Expr::Literal { value, span } => self.literal(value.clone(), *span),
+            Expr::Logical { left, right, operator, span } => match operator.token_type {
+                TokenType::Or => {
+                    self.expression(left)?;
+                    let end_jump = self.emit(Op::JumpIfTrue(0), *span);
+                    self.emit(Op::Pop, *span);
+                    self.expression(right)?;
+                    self.patch(end_jump);
+                },
+                TokenType::And => {
+                    self.expression(left)?;
+                    self.expression(right)?;
+                    self.emit(Op::And, *span);
+                },
+                ttype => return Err(format!("Invalid token in logical expression: {} {}", ttype, span)),
+            },
+            Expr::Unary { operator, val, span } => {
+                self.expression(val)?;
+                self.emit(Op::Unary(operator.token_type), *span);
+            },
+            Expr::Set { object, name, value, operator, span } => {
+                self.expression(object)?;
+                let name_index = self.current_chunk().name(&name.lexeme);
+                if let Some(operator) = operator {
+                    self.emit(Op::Dup, *span);
+                    self.emit(Op::GetProperty(name_index), name.span());
+                    self.expression(value)?;
+                    self.emit(Op::Binary(operator.token_type), *span);
+                } else {
+                    self.expression(value)?;
+                }
+                self.emit(Op::SetProperty(name_index), name.span());
+            },
+            Expr::Super { keyword, method, slot: _, span: _ } => {
+                self.get_variable("this", keyword.span());
+                self.get_variable("super", keyword.span());
+                let name_index = self.current_chunk().name(&method.lexeme);
+                self.emit(Op::GetSuper(name_index), method.span());
+            },
+            Expr::This { keyword, slot: _, span: _ } => self.get_variable("this", keyword.span()),
+            Expr::Variable { name, slot: _, span } => self.get_variable(&name.lexeme, *span),
+            Expr::Update { target, operator, prefix, span } => {
+                let step = match operator.token_type {
+                    TokenType::PlusPlus => TokenType::Plus,
+                    _ => TokenType::Minus,
+                };
+                match target.as_ref() {
+                    Expr::Get { object, name, span: _ } => {
+                        self.expression(object)?;
+                        let name_index = self.current_chunk().name(&name.lexeme);
+                        self.emit(Op::UpdateProperty { name: name_index, step, prefix: *prefix }, *span);
+                    },
+                    Expr::Variable { name, slot: _, span: var_span } => {
+                        self.get_variable(&name.lexeme, *var_span);
+                        // postfix keeps a copy of the old value under the new one
+                        if !prefix { self.emit(Op::Dup, *span); }
+                        self.literal(LiteralValue::NumberValue(DigitType::u8(1)), *span);
+                        self.emit(Op::Binary(step), *span);
+                        self.set_variable(&name.lexeme, *span);
+                        if !prefix { self.emit(Op::Pop, *span); }
+                    },
+                    _ => return Err(format!("Invalid target for '{}' {}", operator.lexeme, span)),
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // compiles the function into its own chunk and leaves a closure of it on the stack
+    fn function(self: &mut Self, name: &str, params: &[Token], body: &[Stmt], kind: FunctionKind, span: Span) -> Result<(), String> {
+        self.begin_function(name, kind);
+        self.current().function.arity = params.len();
+        self.begin_scope();
+        for param in params {
+            self.add_local(&param.lexeme);
+        }
+        let count = body.len();
+        for (i, stmt) in body.iter().enumerate() {
+            self.statement(stmt, i + 1 == count)?;
+        }
+        let function = self.end_function(span);
+        self.closure(function, span);
+        Ok(())
+    }
+
+    fn class(self: &mut Self, name: &Token, superclass: &Option<Expr>, fields: &[Stmt], methods: &[Stmt], span: Span) -> Result<(), String> {
+        let name_index = self.current_chunk().name(&name.lexeme);
+        self.emit(Op::Class(name_index), span);
+        self.declare(&name.lexeme);
+        self.define(&name.lexeme, span);
+
+        // methods of a subclass capture `super` from a scope around them
+        if let Some(superclass) = superclass {
+            self.expression(superclass)?;
+            self.begin_scope();
+            self.add_local("super");
+            self.get_variable(&name.lexeme, span);
+            self.emit(Op::Inherit, superclass.span());
+        }
+
+        self.get_variable(&name.lexeme, span);
+        if !fields.is_empty() {
+            self.begin_function(&name.lexeme, FunctionKind::Fields);
+            for field in fields {
+                if let Stmt::Var { name, initialiser, span } = field {
+                    self.emit(Op::GetLocal(0), *span);
+                    self.expression(initialiser)?;
+                    let field_index = self.current_chunk().name(&name.lexeme);
+                    self.emit(Op::SetProperty(field_index), name.span());
+                    self.emit(Op::Pop, *span);
+                }
+            }
+            let function = self.end_function(span);
+            self.closure(function, span);
+            self.emit(Op::Fields, span);
+        }
+        for method in methods {
+            if let Stmt::Function { name, params, body, span } = method {
+                let kind = if &*name.lexeme == "init" { FunctionKind::Initialiser } else { FunctionKind::Method };
+                self.function(&name.lexeme, params, body, kind, *span)?;
+                let method_index = self.current_chunk().name(&name.lexeme);
+                self.emit(Op::Method(method_index), *span);
+            }
+        }
+        self.emit(Op::Pop, span);
+
+        if superclass.is_some() { self.end_scope(span); }
+        Ok(())
+    }
+
+    // `break` and `continue` leave the scopes inside of the loop, the locals stay known
+    // to the compiler because the code after the jump is still in those scopes
+    fn loop_jump(self: &mut Self, label: &Option<Token>, span: Span, is_break: bool) -> Result<(), String> {
+        let state = self.current();
+        let index = match label {
+            Some(label) => state.loops.iter().rposition(|l| l.label.as_deref() == Some(&*label.lexeme)),
+            None => state.loops.len().checked_sub(1),
+        };
+        let Some(index) = index else {
+            let keyword = if is_break { "break" } else { "continue" };
+            return Err(format!("Cant {keyword} outside of a loop {span}"));
+        };
+
+        let depth = state.loops[index].depth;
+        let drops: Vec<Op> = state.locals.iter().rev()
+            .take_while(|local| local.depth > depth)
+            .map(|local| if local.captured { Op::CloseUpvalue } else { Op::Pop })
+            .collect();
+        for op in drops {
+            self.emit(op, span);
+        }
+
+        let jump = self.emit(Op::Jump(0), span);
+        let target = &mut self.current().loops[index];
+        if is_break { target.breaks.push(jump); } else { target.continues.push(jump); }
+        Ok(())
+    }
+
+    fn literal(self: &mut Self, value: LiteralValue, span: Span) {
+        let index = self.current_chunk().constant(value);
+        self.emit(Op::Constant(index), span);
+    }
+
+    fn closure(self: &mut Self, function: Function, span: Span) {
+        let functions = &mut self.current_chunk().functions;
+        functions.push(Rc::new(function));
+        let index = (functions.len() - 1) as u32;
+        self.emit(Op::Closure(index), span);
+    }
+
+    fn begin_function(self: &mut Self, name: &str, kind: FunctionKind) {
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initialiser | FunctionKind::Fields => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        self.functions.push(FunctionState {
+            function: Function {
+                name: name.to_string(),
+                arity: 0,
+                chunk: Chunk::default(),
+                captures: vec![],
+            },
+            kind,
+            locals: vec![Local { name: receiver.to_string(), depth: 0, captured: false }],
+            scope_depth: 0,
+            loops: vec![],
+        });
+    }
+
+    // running off the end gives back `Non`, or `this` from an initialiser
+    fn end_function(self: &mut Self, span: Span) -> Function {
+        if self.current().kind == FunctionKind::Initialiser {
+            self.emit(Op::GetLocal(0), span);
+        } else {
+            self.literal(LiteralValue::Non, span);
+        }
+        self.emit(Op::Return, span);
+        self.functions.pop().expect("every function was begun").function
+    }
+
+    fn begin_scope(self: &mut Self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(self: &mut Self, span: Span) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+
+        let mut drops = vec![];
+        while state.locals.last().is_some_and(|local| local.depth > depth) {
+            let local = state.locals.pop().expect("checked above");
+            drops.push(if local.captured { Op::CloseUpvalue } else { Op::Pop });
+        }
+        for op in drops {
+            self.emit(op, span);
+        }
+    }
+
+    // the value for a new local is already sitting in its slot, only globals need storing
+    fn declare(self: &mut Self, name: &str) {
+        if self.current().scope_depth > 0 {
+            self.add_local(name);
+        }
+    }
+
+    fn define(self: &mut Self, name: &str, span: Span) {
+        if self.current().scope_depth == 0 {
+            let name_index = self.current_chunk().name(name);
+            self.emit(Op::DefineGlobal(name_index), span);
+        }
+    }
+
+    fn add_local(self: &mut Self, name: &str) {
+        let state = self.current();
+        let depth = state.scope_depth;
+        state.locals.push(Local { name: name.to_string(), depth, captured: false });
+    }
+
+    fn get_variable(self: &mut Self, name: &str, span: Span) {
+        let op = match self.resolve(name) {
+            Variable::Local(slot) => Op::GetLocal(slot),
+            Variable::Upvalue(index) => Op::GetUpvalue(index),
+            Variable::Global(name_index) => Op::GetGlobal(name_index),
+        };
+        self.emit(op, span);
+    }
+
+    fn set_variable(self: &mut Self, name: &str, span: Span) {
+        let op = match self.resolve(name) {
+            Variable::Local(slot) => Op::SetLocal(slot),
+            Variable::Upvalue(index) => Op::SetUpvalue(index),
+            Variable::Global(name_index) => Op::SetGlobal(name_index),
+        };
+        self.emit(op, span);
+    }
+
+    fn resolve(self: &mut Self, name: &str) -> Variable {
+        let function = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(function, name) {
+            return Variable::Local(slot);
+        }
+        if let Some(index) = self.resolve_upvalue(function, name) {
+            return Variable::Upvalue(index);
+        }
+        Variable::Global(self.current_chunk().name(name))
+    }
+
+    fn resolve_local(self: &Self, function: usize, name: &str) -> Option<u32> {
+        self.functions[function].locals.iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u32)
+    }
+
+    // a local of any function further out gets passed down through the ones in between
+    fn resolve_upvalue(self: &mut Self, function: usize, name: &str) -> Option<u32> {
+        if function == 0 { return None; }
+
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].captured = true;
+            return Some(self.add_capture(function, Capture { is_local: true, index: slot }));
+        }
+        let index = self.resolve_upvalue(function - 1, name)?;
+        Some(self.add_capture(function, Capture { is_local: false, index }))
+    }
+
+    fn add_capture(self: &mut Self, function: usize, capture: Capture) -> u32 {
+        let captures = &mut self.functions[function].function.captures;
+        let existing = captures.iter()
+            .position(|c| c.is_local == capture.is_local && c.index == capture.index);
+        match existing {
+            Some(index) => index as u32,
+            None => {
+                captures.push(capture);
+                (captures.len() - 1) as u32
+            },
+        }
+    }
+
+    fn patch(self: &mut Self, jump: usize) {
+        let chunk = self.current_chunk();
+        let target = chunk.code.len() as u32;
+        chunk.code[jump] = match chunk.code[jump] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfTrue(_) => Op::JumpIfTrue(target),
+            op => unreachable!("{op:?} is not a jump"),
+        };
+    }
+
+    fn emit(self: &mut Self, op: Op, span: Span) -> usize {
+        self.current_chunk().emit(op, span)
+    }
+
+    fn current(self: &mut Self) -> &mut FunctionState {
+        self.functions.last_mut().expect("compiling always happens inside of a function")
+    }
+
+    fn current_chunk(self: &mut Self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+}
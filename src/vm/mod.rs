@@ -0,0 +1,524 @@
+// the bytecode backend, `compile` turns the statements into a function and the `Vm` runs it
+// on one value stack, `raz --vm file.raz` picks it over the tree-walking interpreter
+pub mod chunk;
+pub mod compile;
+pub mod value;
+
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::budget::{self, Budget};
+use crate::call_stack;
+use crate::digit::DigitType;
+use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::functions::func::clock_impl;
+use crate::literals::{CallResult, LiteralValue, RazString};
+use crate::memory::{self, Memory};
+use crate::scanner::{Span, TokenType};
+use crate::vm::chunk::{Chunk, Function, Op};
+use crate::vm::value::{BoundMethod, Class, Closure, Instance, Upvalue, Value};
+
+pub use crate::vm::compile::compile;
+
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: HashMap<Rc<str>, Value>,
+    // captured locals still on the stack, closed once their frame or scope goes away
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    max_depth: usize,
+    meter: budget::Meter,
+    memory: Rc<Memory>,
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // where its slot 0 is in the stack
+    base: usize,
+    call_site: Span,
+}
+
+// the globals point at most of what's left, once they're gone whatever was only left over in cycles gets emptied out too
+impl Drop for Vm {
+    fn drop(&mut self) {
+        self.globals.clear();
+        self.memory.collect();
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut globals = HashMap::new();
+        globals.insert(
+            "clock".into(),
+            Value::Literal(LiteralValue::Callable {
+                name: "clock".into(),
+                arity: 0,
+                func: Rc::new(clock_impl),
+                closure: None,
+            }),
+        );
+
+        Self {
+            stack: vec![],
+            frames: vec![],
+            globals,
+            open_upvalues: vec![],
+            max_depth: call_stack::DEFAULT_MAX_DEPTH,
+            meter: budget::Meter::default(),
+            memory: Memory::new(),
+        }
+    }
+
+    // how many frames `frames` can hold before a call is a stack overflow error. they dont
+    // take any rust stack so the default is only there to catch recursion that never ends
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    // same as the interpreter's, every instruction is a step
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.meter = budget::Meter::new(budget);
+    }
+
+    pub fn take_exceeded(&mut self) -> Option<budget::Exceeded> {
+        self.meter.take_exceeded()
+    }
+
+    // how many bytes of values the program can hold on to, see `memory`
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory.set_limit(limit);
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory.used()
+    }
+
+    // globals stay around between runs, so the prompt can keep using them
+    pub fn interpret(self: &mut Self, script: Rc<Function>) -> Result<(), String> {
+        let outer_memory = memory::switch(Some(self.memory.clone()));
+        let closure = Rc::new(Closure::new(script, vec![]));
+        self.stack.push(Value::Closure(closure.clone()));
+        self.frames.push(Frame { closure, ip: 0, base: 0, call_site: Span::default() });
+
+        let outer = budget::switch(self.meter);
+        let result = self.run(0);
+        self.meter = budget::switch(outer);
+        memory::switch(outer_memory);
+
+        if let Err(err) = result {
+            // the script itself isn't a call
+            let traceback = call_stack::traceback(
+                self.frames.iter().skip(1).rev().map(|frame| (frame.closure.function.name.as_str(), frame.call_site))
+            );
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            return Err(if traceback.is_empty() { err } else { format!("{err}\n{traceback}") });
+        }
+        Ok(())
+    }
+
+    // runs until the frame at `stop_depth` returns, giving back what it returned
+    fn run(self: &mut Self, stop_depth: usize) -> Result<Value, String> {
+        loop {
+            let frame = self.frames.last_mut().expect("the vm always runs inside of a frame");
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+            budget::step(|| self.span())?;
+
+            match op {
+                Op::Constant(index) => {
+                    let value = self.chunk().constants[index as usize].clone();
+                    self.stack.push(Value::Literal(value));
+                },
+                Op::Pop => { self.pop(); },
+                Op::Dup => {
+                    let value = self.peek().clone();
+                    self.stack.push(value);
+                },
+                Op::GetLocal(slot) => {
+                    let value = self.stack[self.frame().base + slot as usize].clone();
+                    self.stack.push(value);
+                },
+                Op::SetLocal(slot) => {
+                    let slot = self.frame().base + slot as usize;
+                    self.stack[slot] = self.peek().clone();
+                },
+                Op::GetUpvalue(index) => {
+                    let upvalue = self.frame().closure.upvalues[index as usize].clone();
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                },
+                Op::SetUpvalue(index) => {
+                    let upvalue = self.frame().closure.upvalues[index as usize].clone();
+                    let value = self.peek().clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                },
+                Op::DefineGlobal(index) => {
+                    let name = self.name(index).clone();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                },
+                Op::GetGlobal(index) => match self.globals.get(self.name(index)) {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(self.error(&format!("Variable {:?} has not been declared", self.name(index)))),
+                },
+                Op::SetGlobal(index) => {
+                    let value = self.peek().clone();
+                    let closure = self.frame().closure.clone();
+                    match self.globals.get_mut(&closure.function.chunk.names[index as usize]) {
+                        Some(global) => *global = value,
+                        None => return Err(self.error(&format!("Variable {:?} has not been declared", self.name(index)))),
+                    }
+                },
+                Op::GetProperty(index) => {
+                    let object = self.pop();
+                    let instance = self.instance(object)?;
+                    let value = self.property(&instance, index)?;
+                    self.stack.push(value);
+                },
+                Op::SetProperty(index) => {
+                    let value = self.pop();
+                    let object = self.pop();
+                    let instance = self.instance(object)?;
+                    instance.set(self.name(index).clone(), value.clone());
+                    self.stack.push(value);
+                },
+                Op::UpdateProperty { name, step, prefix } => {
+                    let object = self.pop();
+                    let instance = self.instance(object)?;
+                    let old_value = self.property(&instance, name)?;
+                    let new_value = self.binary(&old_value, &Value::Literal(LiteralValue::NumberValue(DigitType::u8(1))), step)?;
+                    instance.set(self.name(name).clone(), new_value.clone());
+                    self.stack.push(if prefix { new_value } else { old_value });
+                },
+                Op::GetSuper(index) => {
+                    let superclass = self.pop();
+                    let receiver = self.pop();
+                    let Value::Class(superclass) = superclass else {
+                        return Err(self.error("Cant use 'super' outside of a subclass method"));
+                    };
+                    match superclass.find_method(self.name(index)) {
+                        Some(method) => self.stack.push(Value::BoundMethod(Rc::new(BoundMethod { receiver, method }))),
+                        None => return Err(self.error(&format!(
+                            "Superclass {} has no method {:?}", superclass.name, self.name(index)
+                        ))),
+                    }
+                },
+                Op::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = self.binary(&left, &right, operator)?;
+                    self.stack.push(value);
+                },
+                Op::Unary(operator) => {
+                    let value = self.pop();
+                    let value = self.unary(&value, operator)?;
+                    self.stack.push(value);
+                },
+                Op::And => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Literal(LiteralValue::from_bool(left.is_truthy() && right.is_truthy())));
+                },
+                Op::Interpolate(count) => {
+                    let parts = self.stack.split_off(self.stack.len() - count as usize);
+                    let joined = parts.iter().map(Value::to_string).collect::<String>();
+                    let joined = RazString::new(joined).map_err(|msg| self.error(&msg))?;
+                    self.stack.push(Value::Literal(LiteralValue::StringValue(joined)));
+                },
+                Op::Jump(target) => self.jump(target),
+                Op::JumpIfFalse(target) => if !self.peek().is_truthy() { self.jump(target) },
+                Op::JumpIfTrue(target) => if self.peek().is_truthy() { self.jump(target) },
+                Op::Call(argc) => {
+                    let call_site = self.span();
+                    self.call(argc as usize, call_site)?;
+                },
+                Op::TailCall(argc) => {
+                    let call_site = self.span();
+                    self.tail_call(argc as usize, call_site)?;
+                    // calling something that isnt a raz function already gave back the value
+                    if self.frames.len() == stop_depth {
+                        return Ok(self.pop());
+                    }
+                },
+                Op::Closure(index) => {
+                    memory::check().map_err(|msg| self.error(&msg))?;
+                    let function = self.chunk().functions[index as usize].clone();
+                    let (base, enclosing) = (self.frame().base, self.frame().closure.clone());
+                    let upvalues = function.captures.iter().map(|capture| {
+                        if capture.is_local { self.capture(base + capture.index as usize) }
+                        else { enclosing.upvalues[capture.index as usize].clone() }
+                    }).collect();
+                    self.stack.push(Value::Closure(Rc::new(Closure::new(function, upvalues))));
+                },
+                Op::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                },
+                Op::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("returning from a running frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == stop_depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                },
+                Op::Print => {
+                    let value = self.pop();
+                    println!("\"{}\"", value.to_string());
+                },
+                Op::Class(index) => {
+                    let class = Class {
+                        name: self.name(index).clone(),
+                        superclass: RefCell::new(None),
+                        methods: RefCell::new(HashMap::new()),
+                        fields: RefCell::new(None),
+                    };
+                    self.stack.push(Value::Class(Rc::new(class)));
+                },
+                Op::Inherit => {
+                    let class = self.pop();
+                    match (self.peek(), &class) {
+                        (Value::Class(superclass), Value::Class(class)) if Rc::ptr_eq(superclass, class) => {
+                            return Err(self.error(&format!("Class {} cant inherit from itself", class.name)));
+                        },
+                        (Value::Class(superclass), Value::Class(class)) => {
+                            *class.superclass.borrow_mut() = Some(superclass.clone());
+                        },
+                        (other, Value::Class(class)) => return Err(self.error(&format!(
+                            "Superclass of {} has to be a class, got {}", class.name, other.to_type()
+                        ))),
+                        _ => unreachable!("only classes inherit"),
+                    }
+                },
+                Op::Method(index) => {
+                    let (Value::Closure(method), Value::Class(class)) = (self.pop(), self.peek()) else {
+                        unreachable!("methods are compiled right after their class");
+                    };
+                    class.methods.borrow_mut().insert(self.name(index).clone(), method);
+                },
+                Op::Fields => {
+                    let (Value::Closure(fields), Value::Class(class)) = (self.pop(), self.peek()) else {
+                        unreachable!("fields are compiled right after their class");
+                    };
+                    *class.fields.borrow_mut() = Some(fields);
+                },
+            }
+        }
+    }
+
+    // the callee sits under its arguments, whatever the call gives back takes its place
+    fn call(self: &mut Self, argc: usize, call_site: Span) -> Result<(), String> {
+        // besides strings what keeps growing gets made by calls and closures, those check the memory limit
+        memory::check().map_err(|msg| format!("{msg} {call_site}"))?;
+        let base = self.stack.len() - argc - 1;
+        match self.stack[base].clone() {
+            Value::Closure(closure) => self.call_closure(closure, argc, call_site),
+            Value::BoundMethod(bound) => {
+                self.stack[base] = bound.receiver.clone();
+                self.call_closure(bound.method.clone(), argc, call_site)
+            },
+            Value::Class(class) => self.instantiate(class, argc, call_site),
+            Value::Literal(LiteralValue::Callable { name, arity, func, closure: _ }) => {
+                Self::check_arity("Callable", &name, arity, argc, call_site)?;
+                let args = self.stack.split_off(base + 1).into_iter().map(|arg| match arg {
+                    Value::Literal(value) => Ok(value),
+                    other => Err(format!("Cant pass {} to {} {}", other.to_type(), name, call_site)),
+                }).collect::<Result<Vec<LiteralValue>, String>>()?;
+                self.pop();
+
+                match func(Rc::new(RefCell::new(Environment::new())), &args)? {
+                    CallResult::Value(value) => self.stack.push(Value::Literal(value)),
+                    CallResult::TailCall { .. } => unreachable!("only raz functions make tail calls"),
+                }
+                Ok(())
+            },
+            other => Err(format!("{} is not callable. {}", other.to_type(), call_site)),
+        }
+    }
+
+    fn call_closure(self: &mut Self, closure: Rc<Closure>, argc: usize, call_site: Span) -> Result<(), String> {
+        let function = &closure.function;
+        Self::check_arity("Callable", &function.name, function.arity, argc, call_site)?;
+        // the script's own frame doesnt count
+        if self.frames.len() > self.max_depth {
+            return Err(format!(
+                "Stack overflow, calling {} goes deeper than {} calls {}", function.name, self.max_depth, call_site
+            ));
+        }
+
+        let base = self.stack.len() - argc - 1;
+        self.frames.push(Frame { closure, ip: 0, base, call_site });
+        Ok(())
+    }
+
+    // `return f(...)`, the function returning it is done, so whatever gets called takes its place
+    fn tail_call(self: &mut Self, argc: usize, call_site: Span) -> Result<(), String> {
+        let finished = self.frames.pop().expect("tail calls happen inside of a function");
+        self.close_upvalues(finished.base);
+        let base = self.stack.len() - argc - 1;
+        self.stack.drain(finished.base..base);
+        self.call(argc, call_site)
+    }
+
+    // the instance takes the slot the class was called from, so it's slot 0 (`this`) of the
+    // `fields` closures and of `init`, and what's left on the stack once the call is over
+    fn instantiate(self: &mut Self, class: Rc<Class>, argc: usize, call_site: Span) -> Result<(), String> {
+        let init = class.find_method("init");
+        let arity = init.as_ref().map_or(0, |init| init.function.arity);
+        Self::check_arity("Class", &class.name, arity, argc, call_site)?;
+
+        let instance = Value::Instance(Rc::new(Instance::new(class.clone())));
+        let base = self.stack.len() - argc - 1;
+        self.stack[base] = instance.clone();
+
+        // walking up `superclass` gives the classes subclass first, their `fields` closures get
+        // run the other way round and each one to its end with `run` before the next is called
+        let mut classes = vec![];
+        let mut current = Some(class);
+        while let Some(c) = current {
+            current = c.superclass.borrow().clone();
+            classes.push(c);
+        }
+        for c in classes.iter().rev() {
+            let fields = c.fields.borrow().clone();
+            if let Some(fields) = fields {
+                self.stack.push(instance.clone());
+                self.call_closure(fields, 0, call_site)?;
+                self.run(self.frames.len() - 1)?;
+            }
+        }
+
+        match init {
+            Some(init) => self.call_closure(init, argc, call_site),
+            None => Ok(()),
+        }
+    }
+
+    fn check_arity(kind: &str, name: &str, arity: usize, argc: usize, call_site: Span) -> Result<(), String> {
+        if argc == arity { return Ok(()); }
+        Err(format!("{kind} {name} expected {arity} arguments but got {argc}. {call_site}"))
+    }
+
+    fn instance(self: &Self, object: Value) -> Result<Rc<Instance>, String> {
+        match object {
+            Value::Instance(instance) => Ok(instance),
+            other => Err(self.error(&format!("Only instances have properties, got {}", other.to_type()))),
+        }
+    }
+
+    // a `BoundMethod` only gets made when `instance.fields` has nothing by that name
+    fn property(self: &Self, instance: &Rc<Instance>, index: u32) -> Result<Value, String> {
+        let name = self.name(index);
+        if let Some(value) = instance.fields.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        match instance.class.find_method(name) {
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod { receiver: Value::Instance(instance.clone()), method }))),
+            None => Err(self.error(&format!("{} has no property {:?}", instance.class.name, name))),
+        }
+    }
+
+    // the operators on plain values are the interpreter's, the vm's own values can only be compared
+    fn binary(self: &Self, left: &Value, right: &Value, operator: TokenType) -> Result<Value, String> {
+        let result = match (left, right, operator) {
+            (Value::Literal(x), Value::Literal(y), _) => Expr::binary_op(x, y, operator).map(Value::Literal),
+            (x, y, TokenType::EqualEqual) => Ok(Value::Literal(LiteralValue::from_bool(x == y))),
+            (x, y, TokenType::BangEqual) => Ok(Value::Literal(LiteralValue::from_bool(x != y))),
+            (x, y, t_type) => Err(format!(
+                "{} is not implemented for operands {:?} and {:?}", t_type, x.to_string(), y.to_string()
+            )),
+        };
+        result.map_err(|msg| self.error(&msg))
+    }
+
+    fn unary(self: &Self, value: &Value, operator: TokenType) -> Result<Value, String> {
+        let result = match (value, operator) {
+            (value, TokenType::Bang) => Ok(Value::Literal(LiteralValue::from_bool(!value.is_truthy()))),
+            (Value::Literal(value), _) => Expr::unary_op(value, operator).map(Value::Literal),
+            (value, TokenType::Minus) => Err(format!("Minus not implemented for {:?}", value.to_type())),
+            (_, t_type) => Err(format!("{} is not a valid unary operator", t_type)),
+        };
+        result.map_err(|msg| self.error(&msg))
+    }
+
+    // the same captured variable is shared by every closure that captures it
+    fn capture(self: &mut Self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let open = self.open_upvalues.iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open_slot) if open_slot == slot));
+        if let Some(upvalue) = open {
+            return upvalue.clone();
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    // everything captured from `from` upwards is about to be popped, the closures keep the values
+    fn close_upvalues(self: &mut Self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+            if slot < from { return true; }
+            let value = stack[slot].clone();
+            // a closure or instance kept in a captured variable can be what captured it
+            if value.can_point_back() {
+                let weak: Weak<RefCell<Upvalue>> = Rc::downgrade(upvalue);
+                memory::suspect(weak);
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(value);
+            false
+        });
+    }
+
+    fn jump(self: &mut Self, target: u32) {
+        self.frames.last_mut().expect("the vm always runs inside of a frame").ip = target as usize;
+    }
+
+    fn frame(self: &Self) -> &Frame {
+        self.frames.last().expect("the vm always runs inside of a frame")
+    }
+
+    fn chunk(self: &Self) -> &Chunk {
+        &self.frame().closure.function.chunk
+    }
+
+    fn name(self: &Self, index: u32) -> &Rc<str> {
+        &self.chunk().names[index as usize]
+    }
+
+    // where the instruction being run came from
+    fn span(self: &Self) -> Span {
+        let frame = self.frame();
+        frame.closure.function.chunk.spans[frame.ip - 1]
+    }
+
+    fn error(self: &Self, msg: &str) -> String {
+        format!("{msg} {}", self.span())
+    }
+
+    fn peek(self: &Self) -> &Value {
+        self.stack.last().expect("the compiler keeps the stack balanced")
+    }
+
+    fn pop(self: &mut Self) -> Value {
+        self.stack.pop().expect("the compiler keeps the stack balanced")
+    }
+}
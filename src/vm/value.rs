@@ -0,0 +1,225 @@
+use std::rc::{Rc, Weak};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use crate::literals::LiteralValue;
+use crate::memory::{self, Charge, Collect};
+use crate::vm::chunk::Function;
+
+// what the vm works with, the plain values are the same ones the interpreter uses
+// so the operators can be shared, functions and classes are the vm's own
+#[derive(Clone)]
+pub enum Value {
+    Literal(LiteralValue),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+// closures and instances count against the memory limit like the interpreter's environments and instances do
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    _charge: Charge,
+}
+
+impl Closure {
+    pub fn new(function: Rc<Function>, upvalues: Vec<Rc<RefCell<Upvalue>>>) -> Self {
+        let charge = Charge::new(std::mem::size_of::<Self>() + upvalues.len() * std::mem::size_of::<Upvalue>());
+        Self { function, upvalues, _charge: charge }
+    }
+}
+
+// a captured variable, it points into the stack while the function that declared it is running
+// and holds the value itself once that function is gone
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Class {
+    pub name: Rc<str>,
+    pub superclass: RefCell<Option<Rc<Class>>>,
+    pub methods: RefCell<HashMap<Rc<str>, Rc<Closure>>>,
+    // sets up the `var x = 0;` fields of a new instance, with the instance as `this`
+    pub fields: RefCell<Option<Rc<Closure>>>,
+}
+
+impl Class {
+    // looks through the superclasses too
+    pub fn find_method(self: &Self, name: &str) -> Option<Rc<Closure>> {
+        if let Some(method) = self.methods.borrow().get(name) {
+            return Some(method.clone());
+        }
+        self.superclass.borrow().as_ref().and_then(|superclass| superclass.find_method(name))
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Rc<str>, Value>>,
+    charge: RefCell<Charge>,
+    // it's been handed to `memory::suspect` already
+    suspected: Cell<bool>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        let charge = RefCell::new(Charge::new(std::mem::size_of::<Self>()));
+        Self { class, fields: RefCell::new(HashMap::new()), charge, suspected: Cell::new(false) }
+    }
+
+    // same as the interpreter's, a field pointing at something that can point back might make a cycle
+    pub fn set(self: &Rc<Self>, name: Rc<str>, value: Value) {
+        if !self.suspected.get() && value.can_point_back() {
+            self.suspected.set(true);
+            let instance: Weak<Instance> = Rc::downgrade(self);
+            memory::suspect(instance);
+        }
+        if self.fields.borrow_mut().insert(name, value).is_none() {
+            self.charge.borrow_mut().grow(std::mem::size_of::<Value>());
+        }
+    }
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(x), Self::Literal(y)) => x == y,
+            (Self::Closure(c1), Self::Closure(c2)) => Rc::ptr_eq(c1, c2),
+            (Self::Class(c1), Self::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Self::Instance(i1), Self::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            (Self::BoundMethod(m1), Self::BoundMethod(m2)) => Rc::ptr_eq(m1, m2),
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    pub fn to_string(self: &Self) -> String {
+        match self {
+            Self::Literal(value) => value.to_string(),
+            Self::Closure(closure) => format!("{}_{}", closure.function.name, closure.function.arity),
+            Self::Class(class) => class.name.to_string(),
+            Self::Instance(instance) => format!("{} instance", instance.class.name),
+            Self::BoundMethod(bound) => format!("{}_{}", bound.method.function.name, bound.method.function.arity),
+        }
+    }
+
+    pub fn to_type(self: &Self) -> &str {
+        match self {
+            Self::Literal(value) => value.to_type(),
+            Self::Closure(_) | Self::BoundMethod(_) => "Callable",
+            Self::Class(_) => "Class",
+            Self::Instance(_) => "Instance",
+        }
+    }
+
+    pub fn is_truthy(self: &Self) -> bool {
+        match self {
+            Self::Literal(value) => value.is_truthy() == LiteralValue::True,
+            _ => true,
+        }
+    }
+
+    // the pointers `memory::Collect` follows
+    pub fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) {
+        match self {
+            Self::Literal(value) => value.edges(found),
+            Self::Closure(closure) => found.push(closure.clone()),
+            Self::Class(class) => found.push(class.clone()),
+            Self::Instance(instance) => found.push(instance.clone()),
+            Self::BoundMethod(bound) => found.push(bound.clone()),
+        }
+    }
+
+    pub fn can_point_back(self: &Self) -> bool {
+        !matches!(self, Self::Literal(_))
+    }
+}
+
+// a closure can end up holding itself through what it captured, like a local function calling itself.
+// emptying out the upvalues and instances in a cycle is enough to break it
+impl Collect for Closure {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        for upvalue in &self.upvalues {
+            found.push(upvalue.clone());
+        }
+        true
+    }
+
+    fn clear(self: &Self) {}
+}
+
+impl Collect for RefCell<Upvalue> {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        let Ok(upvalue) = self.try_borrow() else { return false };
+        // an open one points into the stack, that's in use either way
+        if let Upvalue::Closed(value) = &*upvalue {
+            value.edges(found);
+        }
+        true
+    }
+
+    fn clear(self: &Self) {
+        let Ok(mut upvalue) = self.try_borrow_mut() else { return };
+        let value = std::mem::replace(&mut *upvalue, Upvalue::Closed(Value::Literal(LiteralValue::Non)));
+        drop(upvalue);
+        drop(value);
+    }
+}
+
+impl Collect for Class {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        let (Ok(superclass), Ok(methods), Ok(fields)) =
+            (self.superclass.try_borrow(), self.methods.try_borrow(), self.fields.try_borrow()) else { return false };
+        if let Some(superclass) = &*superclass {
+            found.push(superclass.clone());
+        }
+        for method in methods.values().chain(fields.iter()) {
+            found.push(method.clone());
+        }
+        true
+    }
+
+    fn clear(self: &Self) {
+        let (Ok(mut methods), Ok(mut fields)) = (self.methods.try_borrow_mut(), self.fields.try_borrow_mut()) else { return };
+        let dropped = (std::mem::take(&mut *methods), fields.take());
+        drop((methods, fields));
+        drop(dropped);
+    }
+}
+
+impl Collect for Instance {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        let Ok(fields) = self.fields.try_borrow() else { return false };
+        found.push(self.class.clone());
+        for value in fields.values() {
+            value.edges(found);
+        }
+        true
+    }
+
+    fn clear(self: &Self) {
+        let Ok(mut fields) = self.fields.try_borrow_mut() else { return };
+        let values = std::mem::take(&mut *fields);
+        drop(fields);
+        drop(values);
+    }
+}
+
+impl Collect for BoundMethod {
+    fn edges(self: &Self, found: &mut Vec<Rc<dyn Collect>>) -> bool {
+        self.receiver.edges(found);
+        found.push(self.method.clone());
+        true
+    }
+
+    fn clear(self: &Self) {}
+}